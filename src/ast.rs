@@ -1,18 +1,28 @@
-use std::{collections::HashMap, ops::Range, rc::Rc};
-use lazy_static::lazy_static;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
 
 use num_rational::Rational64;
+use num_traits::Signed;
 
-use crate::{LocatableContent, Location, runtime, tokenize::{Punct, Token, TokenContent, TokenStream}};
+use crate::{LocatableContent, Location, MAX_DEPTH, runtime, tokenize::{Punct, Token, TokenContent, TokenStream}};
 use crate::error::{CompilationError, CompilationErrorKind};
-use crate::tokenize::{DelimiterType, Ident};
+use crate::format_spec::FormatSpec;
+use crate::formatter::Printer;
+use crate::number::Number;
+use crate::runtime::{AngleMode, Runtime, runtime_errors::{ArithmeticError, IndexError, ShapeError, TypeError}};
+use crate::tokenize::{self, DelimiterType, Ident, StrPart};
 
 pub type CodeObject = LocatableContent<CodeObjectContent>;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum CodeObjectContent {
     // Value(Value),
     ExecuteFn(FnInfo),
     // Parenthases(Code),
+    Expression(Expression),
+    Assign(Ident, Expression),
+    /// `name(params) = clauses`, where the clauses are tried in order
+    DefineFn(Ident, Vec<Ident>, Vec<Clause>),
 }
 
 pub type Expression = LocatableContent<ExpressionContent>;
@@ -20,42 +30,192 @@ pub type Expression = LocatableContent<ExpressionContent>;
 // impl Expression {
 //     fn eval(&self) -> runtime::Result<Value> {
 //         use ExpressionContent::*;
-        
-        
+
+
 //     }
 // }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExpressionContent {
     ExecuteFn(FnInfo),
+    /// An expression grouped by any kind of bracket
     Parenthases(Box<Expression>),
     Literal(SimpleValue),
-    BinaryExpr(BinaryExpr, Box<[Expression; 2]>)
+    /// A string literal
+    Str(String),
+    /// A string literal with holes, `"x = {x:.3}"`
+    Interpolated(Vec<InterpolatedPart>),
+    Variable(Ident),
+    UnaryExpr(UnaryExpr, Box<Expression>),
+    BinaryExpr(BinaryExpr, Box<[Expression; 2]>),
+    /// `if condition then a else b`, or `condition ? a : b`
+    Conditional(Box<[Expression; 3]>),
+    /// `[a, b, c]`. Brackets around a single expression only group it, as
+    /// in textbook formulas, so a list with one element is written `[a,]`
+    List(Vec<Expression>),
+    /// `list[index]`
+    Index(Box<[Expression; 2]>),
+    /// `start..end`, or `start..=end` to include `end`, optionally followed
+    /// by `step size`
+    Range {
+        bounds: Box<[Expression; 2]>,
+        step: Option<Box<Expression>>,
+        inclusive: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolatedPart {
+    Text(String),
+    /// An expression whose value is written into the string
+    Hole(Expression, Option<FormatSpec>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnaryExpr {
+    Negate,
+    Not,
+    /// An angle in degrees, converted to the runtime's angle mode
+    Degrees,
+    Factorial,
+    DoubleFactorial,
+}
+
+impl UnaryExpr {
+    pub fn eval(&self, operand: &Value, runtime: &Runtime, location: &Location) -> runtime::Result<Value> {
+        use UnaryExpr::*;
+
+        if let Value::List(items) = operand {
+            return items.iter().map(|item| self.eval(item, runtime, location)).collect::<runtime::Result<_>>().map(Value::List);
+        }
+
+        if self == &Not {
+            return Ok(Value::bool(!operand.as_bool(location)?));
+        }
+
+        let operand = operand.as_number(location)?;
+        let operand = &operand;
+        match self {
+            Negate => operand.neg(),
+            Not => unreachable!(),
+            Degrees => AngleMode::Degrees.angle_to_radians(operand)
+                .and_then(|radians| runtime.angle_mode().radians_to_angle(&radians)),
+            Factorial => operand.factorial(runtime.precision(), || runtime.step(location))?,
+            DoubleFactorial => operand.double_factorial(runtime.precision(), || runtime.step(location))?,
+        }
+            .and_then(|n| n.check_size(runtime.precision()))
+            .map(|n| Value::Simple(SimpleValue::Number(n)))
+            .map_err(|e| Box::new(ArithmeticError::new(LocatableContent::new(e, *location))) as Box<_>)
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        use UnaryExpr::*;
+
+        match self {
+            Negate => "-",
+            Not => "not",
+            Degrees => "°",
+            Factorial => "!",
+            DoubleFactorial => "!!",
+        }
+    }
+
+    /// Whether the operator comes after its operand
+    pub fn is_postfix(&self) -> bool {
+        matches!(self, UnaryExpr::Degrees | UnaryExpr::Factorial | UnaryExpr::DoubleFactorial)
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BinaryExpr {
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// Floored modulo, the result has the sign of the divisor
+    Modulo,
+    /// Truncated remainder, the result has the sign of the dividend
+    Remainder,
+    /// Division rounded down to an integer, written `div`, as `//` starts a
+    /// comment
+    FloorDivide,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
 }
 
 impl BinaryExpr {
-    pub fn eval(&self, left: &Value, right: &Value) -> runtime::Result<Value> {
+    pub fn eval(&self, left: &Value, right: &Value, runtime: &Runtime, location: &Location) -> runtime::Result<Value> {
         use BinaryExpr::*;
 
-        #[allow(irrefutable_let_patterns)]
-        if let (Value::Simple(SimpleValue::Number(left)), Value::Simple(SimpleValue::Number(right))) = (left, right) {
-            Ok(Value::number(match self {
-                Add => left + right,
-                Subtract => left - right,
-                Multiply => left * right,
-                Divide => left / right,
-            }))
+        match self {
+            Equal => return Ok(Value::bool(left.equals(right))),
+            NotEqual => return Ok(Value::bool(!left.equals(right))),
+            And => return Ok(Value::bool(left.as_bool(location)? && right.as_bool(location)?)),
+            Or => return Ok(Value::bool(left.as_bool(location)? || right.as_bool(location)?)),
+            _ => {},
         }
-        else {
-            todo!();
+
+        // Operators apply element-wise to lists, and a list combined with
+        // anything else applies the operator to each element
+        match (left, right) {
+            (Value::List(left), Value::List(right)) => {
+                if left.len() != right.len() {
+                    return Err(Box::new(ShapeError::new(left.len(), right.len(), *location)));
+                }
+                return left.iter().zip(right)
+                    .map(|(left, right)| self.eval(left, right, runtime, location))
+                    .collect::<runtime::Result<_>>()
+                    .map(Value::List);
+            },
+            (Value::List(left), right) => return left.iter()
+                .map(|left| self.eval(left, right, runtime, location))
+                .collect::<runtime::Result<_>>()
+                .map(Value::List),
+            (left, Value::List(right)) => return right.iter()
+                .map(|right| self.eval(left, right, runtime, location))
+                .collect::<runtime::Result<_>>()
+                .map(Value::List),
+            _ => {},
+        }
+
+        // Adding anything to a string appends it to the string
+        match (self, left, right) {
+            (Add, Value::Str(left), right) => return Ok(Value::Str(format!("{left}{right}"))),
+            (Add, left, Value::Str(right)) => return Ok(Value::Str(format!("{left}{right}"))),
+            _ => {},
         }
+
+        let (left, right) = (&left.as_number(location)?, &right.as_number(location)?);
+        match self {
+            Add => left.add(right),
+            Subtract => left.sub(right),
+            Multiply => left.mul(right),
+            Divide => left.div(right),
+            Modulo => left.modulo(right),
+            Remainder => left.rem(right),
+            FloorDivide => left.floor_div(right),
+            Power => left.pow(right, runtime.precision()),
+            Equal | NotEqual | And | Or => unreachable!(),
+            Less | LessOrEqual | Greater | GreaterOrEqual => {
+                let ordering = left.compare(right);
+                return Ok(Value::bool(match self {
+                    Less => ordering == Some(Ordering::Less),
+                    LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Greater => ordering == Some(Ordering::Greater),
+                    _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                }));
+            },
+        }
+            .and_then(|n| n.check_size(runtime.precision()))
+            .map(|n| Value::Simple(SimpleValue::Number(n)))
+            .map_err(|e| Box::new(ArithmeticError::new(LocatableContent::new(e, *location))) as Box<_>)
     }
 
     pub fn symbol(&self) -> &'static str {
@@ -66,6 +226,18 @@ impl BinaryExpr {
             Subtract => "-",
             Multiply => "*",
             Divide => "/",
+            Modulo => "%",
+            Remainder => "rem",
+            FloorDivide => "div",
+            Power => "^",
+            Equal => "==",
+            NotEqual => "!=",
+            Less => "<",
+            LessOrEqual => "<=",
+            Greater => ">",
+            GreaterOrEqual => ">=",
+            And => "and",
+            Or => "or",
         }
     }
 
@@ -73,99 +245,737 @@ impl BinaryExpr {
         use BinaryExpr::*;
 
         match self {
-            Add | Subtract => 1,
-            Multiply | Divide => 2,
+            Or => 1,
+            And => 2,
+            Equal | NotEqual | Less | LessOrEqual | Greater | GreaterOrEqual => 3,
+            Add | Subtract => 4,
+            Multiply | Divide | Modulo | Remainder | FloorDivide => 5,
+            Power => 6,
+        }
+    }
+
+    /// Whether `a op b op c` means `a op (b op c)` rather than `(a op b) op c`
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinaryExpr::Power)
+    }
+
+    fn from_token(token: &TokenContent) -> Option<Self> {
+        use BinaryExpr::*;
+
+        match token {
+            TokenContent::Punct(Punct::Plus) => Some(Add),
+            TokenContent::Punct(Punct::Dash) => Some(Subtract),
+            TokenContent::Punct(Punct::Asterisk) => Some(Multiply),
+            TokenContent::Punct(Punct::Slash) => Some(Divide),
+            TokenContent::Punct(Punct::Percent) => Some(Modulo),
+            TokenContent::Punct(Punct::Caret) => Some(Power),
+            TokenContent::Punct(Punct::DoubleEquals) => Some(Equal),
+            TokenContent::Punct(Punct::BangEquals) => Some(NotEqual),
+            TokenContent::Punct(Punct::Less) => Some(Less),
+            TokenContent::Punct(Punct::LessEquals) => Some(LessOrEqual),
+            TokenContent::Punct(Punct::Greater) => Some(Greater),
+            TokenContent::Punct(Punct::GreaterEquals) => Some(GreaterOrEqual),
+            TokenContent::Punct(Punct::DoubleAmpersand) => Some(And),
+            TokenContent::Punct(Punct::DoublePipe) => Some(Or),
+            TokenContent::Ident(ident) => match ident.as_str() {
+                "mod" => Some(Modulo),
+                "rem" => Some(Remainder),
+                "div" => Some(FloorDivide),
+                "and" => Some(And),
+                "or" => Some(Or),
+                _ => None,
+            },
+            _ => None,
         }
     }
 }
 
 type FnInfo = (Ident, Vec<Expression>);
 
+/// A body, and the guard which must be true for the body to be used. A
+/// clause without a guard always matches.
+pub type Clause = (Expression, Option<Expression>);
+
 pub type Code = Vec<CodeObject>;
 pub type Args = Vec<Value>;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SimpleValue {
-    Number(Rational64),
+    Number(Number),
+    Bool(bool),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Simple(SimpleValue),
+    List(Vec<Value>),
+    /// A function referred to by name, e.g. `f` in `seq(f, 10)`
+    Function(Ident),
+    Str(String),
 }
 
 impl Value {
     pub fn number(value: Rational64) -> Value {
-        Value::Simple(SimpleValue::Number(value))
-    }
-}
-
-
-fn parse(stream: TokenStream) -> Result<Code, CompilationError> {
-    let mut code = Code::new();
-    let mut iter = stream.into_iter();
-
-    while let Some(token) = iter.next()  {
-        if let TokenContent::Ident(ident) = token.content() {
-            let maybe_args = iter.next().unwrap();
-            if let Token { content: TokenContent::Block(DelimiterType::Parenthases, args), location: arg_location } = maybe_args {
-                let mut parsed_args = Vec::new();
-                let mut last_comma = None;
-                for e in args.iter().enumerate() {
-                    if e.1.content() == &TokenContent::Punct(Punct::Comma) {
-                        let arg_start = last_comma.map(|n| n + 1).unwrap_or(0);
-                        if e.0 == arg_start {
-                            return Err(CompilationError::new(e.1.location, CompilationErrorKind::TwoCommas));
-                        }
-                        else {
-                            parsed_args.push(eval_expr(&args[arg_start..e.0]));
-                            last_comma = Some(e.0);
-                        }
-                    }
-                }
+        Value::Simple(SimpleValue::Number(Number::Exact(value)))
+    }
+
+    pub fn bool(value: bool) -> Value {
+        Value::Simple(SimpleValue::Bool(value))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Simple(SimpleValue::Number(_)) => "number",
+            Value::Simple(SimpleValue::Bool(_)) => "boolean",
+            Value::List(_) => "list",
+            Value::Function(_) => "function",
+            Value::Str(_) => "string",
+        }
+    }
+
+    pub fn as_number(&self, location: &Location) -> runtime::Result<Number> {
+        match self {
+            Value::Simple(SimpleValue::Number(n)) => Ok(n.clone()),
+            _ => Err(Box::new(TypeError::new("number", self.type_name(), *location))),
+        }
+    }
+
+    pub fn as_bool(&self, location: &Location) -> runtime::Result<bool> {
+        match self {
+            Value::Simple(SimpleValue::Bool(b)) => Ok(*b),
+            _ => Err(Box::new(TypeError::new("boolean", self.type_name(), *location))),
+        }
+    }
+
+    /// A non-negative integer, such as a count or a size
+    pub fn as_count(&self, location: &Location) -> runtime::Result<usize> {
+        match self.as_number(location)? {
+            Number::Exact(n) if n.is_integer() && !n.is_negative() => Ok(n.to_integer() as usize),
+            _ => Err(Box::new(TypeError::new("non-negative integer", self.type_name(), *location))),
+        }
+    }
+
+    pub fn as_integer(&self, location: &Location) -> runtime::Result<i64> {
+        match self.as_number(location)? {
+            Number::Exact(n) if n.is_integer() => Ok(n.to_integer()),
+            _ => Err(Box::new(TypeError::new("integer", self.type_name(), *location))),
+        }
+    }
+
+    pub fn as_str(&self, location: &Location) -> runtime::Result<&str> {
+        match self {
+            Value::Str(string) => Ok(string),
+            _ => Err(Box::new(TypeError::new("string", self.type_name(), *location))),
+        }
+    }
+
+    pub fn as_list(&self, location: &Location) -> runtime::Result<&[Value]> {
+        match self {
+            Value::List(items) => Ok(items),
+            _ => Err(Box::new(TypeError::new("list", self.type_name(), *location))),
+        }
+    }
+
+    /// The element of a list at a zero-based index
+    pub fn index(&self, index: &Value, location: &Location) -> runtime::Result<Value> {
+        let items = self.as_list(location)?;
+        let index = index.as_number(location)?;
+        match &index {
+            Number::Exact(i) if i.is_integer() && !i.is_negative() && (i.to_integer() as u64) < items.len() as u64
+                => Ok(items[i.to_integer() as usize].clone()),
+            _ => Err(Box::new(IndexError::new(index, items.len(), *location))),
+        }
+    }
+
+    /// Whether two values are equal. Numbers are compared by value, so
+    /// `1/2 == 0.5`, and values of different types are never equal
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Simple(SimpleValue::Number(a)), Value::Simple(SimpleValue::Number(b))) => a.compare(b) == Some(Ordering::Equal),
+            (Value::Simple(SimpleValue::Bool(a)), Value::Simple(SimpleValue::Bool(b))) => a == b,
+            (Value::List(a), Value::List(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b)),
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Writes the expression as calculator code, with only the parentheses
+/// needed to keep its meaning
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Printer::new(None).expression(self))
+    }
+}
+
+impl Display for CodeObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Printer::new(None).statement(self))
+    }
+}
+
+impl Display for SimpleValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SimpleValue::Number(n) => write!(f, "{n}"),
+            SimpleValue::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Simple(value) => write!(f, "{value}"),
+            Value::List(items) => {
+                // Strings are quoted inside a list so that the elements can be told apart
+                let items = items.iter()
+                    .map(|item| match item {
+                        Value::Str(string) => format!("{string:?}"),
+                        item => item.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Value::Str(string) => write!(f, "{string}"),
+            Value::Function(name) => write!(f, "<function {name}>"),
+        }
+    }
+}
+
+
+pub fn parse(stream: TokenStream) -> Result<Code, CompilationError> {
+    let end = stream.last().map_or_else(Location::default, |token| *token.location());
+    Parser::new(&stream, end, 0).parse_code()
+}
+
+/// Words with a meaning of their own, which can't be used as an operand
+const KEYWORDS: &[&str] = &["mod", "rem", "div", "and", "or", "not", "if", "then", "else", "when", "otherwise", "step"];
+
+/// An expression and its height, the number of expressions on the longest
+/// path down from it, counting itself
+type Nested = (Expression, usize);
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// Where the tokens end, used as the location of errors at the end of input
+    end: Location,
+    /// How many expressions the one being parsed is known to be inside, which
+    /// keeps the parser from recursing too deeply. Chains of operators are
+    /// built in a loop, so they are limited by checking heights instead.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token], end: Location, depth: usize) -> Self {
+        Self { tokens, position: 0, end, depth }
+    }
+
+    fn current(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position).filter(|token| token.content() != &TokenContent::EOF)
+    }
+
+    fn is_done(&self) -> bool {
+        self.current().is_none()
+    }
+
+    fn is_punct(&self, punct: Punct) -> bool {
+        self.current().is_some_and(|token| token.content() == &TokenContent::Punct(punct))
+    }
+
+    fn eat_punct(&mut self, punct: Punct) -> bool {
+        let is_punct = self.is_punct(punct);
+        if is_punct {
+            self.position += 1;
+        }
+        is_punct
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.current().is_some_and(|token| matches!(token.content(), TokenContent::Ident(ident) if ident == keyword))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), CompilationError> {
+        if self.is_keyword(keyword) {
+            self.position += 1;
+            Ok(())
+        }
+        else {
+            Err(self.syntax_error(&[keyword]))
+        }
+    }
 
-                code.push(CodeObject::new(CodeObjectContent::ExecuteFn((ident.clone(), parsed_args)), (*token.location()..=arg_location).into()));
+    fn eat_separator(&mut self) -> bool {
+        let is_separator = self.is_punct(Punct::Semicolon)
+            || self.current().is_some_and(|token| token.content() == &TokenContent::Newline);
+        if is_separator {
+            self.position += 1;
+        }
+        is_separator
+    }
+
+    fn too_deep(&self) -> CompilationError {
+        let location = self.current().map_or(self.end, |token| *token.location());
+        CompilationError::new(location, CompilationErrorKind::TooDeeplyNested)
+    }
+
+    /// Parses something that will be inside a new expression
+    fn nest<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, CompilationError>) -> Result<T, CompilationError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.too_deep());
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// A parser for the tokens in a block or a hole of a string, which will
+    /// be inside a new expression
+    fn sub_parser<'b>(&self, tokens: &'b [Token], end: Location) -> Result<Parser<'b>, CompilationError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.too_deep());
+        }
+        Ok(Parser::new(tokens, end, self.depth + 1))
+    }
+
+    /// Makes an expression out of parts with the given heights, unless that
+    /// would nest them too deeply
+    fn node(content: ExpressionContent, location: Location, heights: impl IntoIterator<Item = usize>) -> Result<Nested, CompilationError> {
+        let height = heights.into_iter().max().unwrap_or(0) + 1;
+        if height > MAX_DEPTH {
+            return Err(CompilationError::new(location, CompilationErrorKind::TooDeeplyNested));
+        }
+        Ok((Expression::new(content, location), height))
+    }
+
+    fn syntax_error(&self, expected: &[&str]) -> CompilationError {
+        let found = self.current().cloned().unwrap_or_else(|| Token::new(TokenContent::EOF, self.end));
+        CompilationError::new(*found.location(), CompilationErrorKind::SyntaxError {
+            expected: expected.iter().map(|s| String::from(*s)).collect(),
+            found,
+        })
+    }
+
+    /// Parses the contents of a block, which must be exactly one expression
+    fn parse_block(&self, ty: DelimiterType, tokens: &[Token], block: &Location) -> Result<Nested, CompilationError> {
+        let mut parser = self.sub_parser(tokens, (block.end() - 1..block.end()).into())?;
+        let expr = parser.parse_expression()?;
+        if parser.is_done() {
+            Ok(expr)
+        }
+        else {
+            Err(parser.syntax_error(&[ty.close()]))
+        }
+    }
+
+    fn parse_code(&mut self) -> Result<Code, CompilationError> {
+        let mut code = Code::new();
+
+        loop {
+            while self.eat_separator() {}
+            if self.is_done() {
+                break;
+            }
+
+            code.push(self.parse_statement()?);
+
+            if !self.is_done() && !self.eat_separator() {
+                return Err(self.syntax_error(&["newline", ";"]));
+            }
+        }
+
+        Ok(code)
+    }
+
+    /// Whether the tokens at the current position start an assignment or a
+    /// function definition
+    fn at_definition(&self) -> bool {
+        let is_equals = |offset| self.tokens.get(self.position + offset)
+            .is_some_and(|token: &Token| token.content() == &TokenContent::Punct(Punct::Equals));
+
+        match self.current().map(Token::content) {
+            Some(TokenContent::Ident(_)) => is_equals(1) || matches!(
+                self.tokens.get(self.position + 1).map(Token::content),
+                Some(TokenContent::Block(DelimiterType::Parenthases, _))
+            ) && is_equals(2),
+            _ => false,
+        }
+    }
+
+    /// Whether the tokens up to the next `;` or line break are a clause with
+    /// `when` or `otherwise`. Anything else after a `;` is a new statement,
+    /// so a catch-all clause after a `;` needs `otherwise`.
+    fn at_clause(&self) -> bool {
+        self.tokens[self.position.min(self.tokens.len())..].iter()
+            .map(Token::content)
+            .take_while(|content| !matches!(content, TokenContent::Newline | TokenContent::Punct(Punct::Semicolon)))
+            .any(|content| matches!(content, TokenContent::Ident(ident) if ident == "when" || ident == "otherwise"))
+    }
+
+    fn parse_statement(&mut self) -> Result<CodeObject, CompilationError> {
+        if let (Some(Token { content: TokenContent::Ident(ident), location }), Some(Token { content: TokenContent::Block(DelimiterType::Parenthases, params), location: params_location }))
+            = (self.current(), self.tokens.get(self.position + 1))
+        {
+            if self.at_definition() {
+                self.position += 3;
+                let params = Parser::new(params, (params_location.end() - 1..params_location.end()).into(), self.depth).parse_params()?;
+                let clauses = self.parse_clauses()?;
+                let location = (*location..=*clauses.last().map(|(body, guard)| guard.as_ref().unwrap_or(body)).unwrap().location()).into();
+                return Ok(CodeObject::new(CodeObjectContent::DefineFn(ident.clone(), params, clauses), location));
+            }
+        }
+
+        if let (Some(Token { content: TokenContent::Ident(ident), location }), Some(TokenContent::Punct(Punct::Equals)))
+            = (self.current(), self.tokens.get(self.position + 1).map(Token::content))
+        {
+            self.position += 2;
+            let (value, _) = self.parse_expression()?;
+            let location = (*location..=*value.location()).into();
+            return Ok(CodeObject::new(CodeObjectContent::Assign(ident.clone(), value), location));
+        }
+
+        let (expr, _) = self.parse_expression()?;
+        Ok(match expr.content {
+            ExpressionContent::ExecuteFn(info) => CodeObject::new(CodeObjectContent::ExecuteFn(info), expr.location),
+            content => {
+                let location = expr.location;
+                CodeObject::new(CodeObjectContent::Expression(Expression::new(content, location)), location)
+            },
+        })
+    }
+
+    /// Parses the clauses of a function definition, `body when guard; ...`.
+    /// Clauses continue after a `;` as long as the previous one had a guard.
+    fn parse_clauses(&mut self) -> Result<Vec<Clause>, CompilationError> {
+        let mut clauses = Vec::new();
+
+        loop {
+            let (body, _) = self.parse_expression()?;
+            let guard = if self.is_keyword("when") {
+                self.position += 1;
+                Some(self.parse_expression()?.0)
             }
             else {
-                return Err(CompilationError::new(maybe_args.location, CompilationErrorKind::SyntaxError {
-                    expected: vec![String::from("(")], found: maybe_args
-                }));
+                if self.is_keyword("otherwise") {
+                    self.position += 1;
+                }
+                None
+            };
+
+            let has_guard = guard.is_some();
+            clauses.push((body, guard));
+
+            if !has_guard || !self.is_punct(Punct::Semicolon) {
+                break;
+            }
+
+            self.position += 1;
+            if !self.at_clause() || self.at_definition() {
+                // The semicolon ends the statement rather than separating clauses
+                self.position -= 1;
+                break;
             }
         }
+
+        Ok(clauses)
     }
 
-    Ok(code)
-}
+    fn parse_params(&mut self) -> Result<Vec<Ident>, CompilationError> {
+        let mut params = Vec::new();
 
-fn eval_expr(stream: &[Token]) -> Expression {
-    lazy_static! {
-        static ref OPERATOR_ORDER: [Vec<Punct>; 2] = [
-            vec![Punct::Asterisk, Punct::Slash],
-            vec![Punct::Plus, Punct::Dash],
-        ];
+        while let Some(token) = self.current() {
+            match token.content() {
+                TokenContent::Ident(ident) => params.push(ident.clone()),
+                _ => return Err(self.syntax_error(&["parameter name"])),
+            }
+            self.position += 1;
 
-        static ref SUPPORTED_OPERATORS: Vec<Punct> = {
-            let mut v = Vec::new();
-            for i in OPERATOR_ORDER.iter() {
-                for j in i {
-                    v.push(*j);
+            if !self.is_done() {
+                if !self.eat_punct(Punct::Comma) {
+                    return Err(self.syntax_error(&[",", ")"]));
+                }
+                else if self.is_done() {
+                    return Err(self.syntax_error(&["parameter name"]));
                 }
             }
-            v
+        }
+
+        Ok(params)
+    }
+
+    fn parse_expression(&mut self) -> Result<Nested, CompilationError> {
+        let (condition, condition_height) = self.parse_binary(0)?;
+
+        if self.eat_punct(Punct::Question) {
+            let (then, then_height) = self.nest(Self::parse_expression)?;
+            if !self.eat_punct(Punct::Colon) {
+                return Err(self.syntax_error(&[":"]));
+            }
+            let (otherwise, otherwise_height) = self.nest(Self::parse_expression)?;
+            let location = (condition.location..=otherwise.location).into();
+            let content = ExpressionContent::Conditional(Box::new([condition, then, otherwise]));
+            Self::node(content, location, [condition_height, then_height, otherwise_height])
+        }
+        else {
+            Ok((condition, condition_height))
+        }
+    }
+
+    /// Whether the current token can be multiplied by juxtaposition, as in
+    /// `2x`, `2 pi` or `(a+b)(a-b)`
+    fn at_implicit_operand(&self) -> bool {
+        match self.current().map(Token::content) {
+            Some(TokenContent::Ident(ident)) => !KEYWORDS.contains(&ident.as_str()),
+            // A bracket after an operand indexes it instead
+            Some(TokenContent::Block(ty, _)) => ty != &DelimiterType::Bracket,
+            _ => false,
+        }
+    }
+
+    /// Parses a series of binary expressions, stopping at any operator with
+    /// a priority lower than `min_priority`
+    fn parse_binary(&mut self, min_priority: u8) -> Result<Nested, CompilationError> {
+        let (mut left, mut height) = self.parse_prefix()?;
+
+        loop {
+            // Ranges bind more loosely than arithmetic but more tightly than
+            // comparisons, so `1..n + 1 == xs` is `(1..(n + 1)) == xs`
+            if min_priority <= BinaryExpr::Add.priority()
+                && (self.is_punct(Punct::DoubleDot) || self.is_punct(Punct::DoubleDotEquals))
+            {
+                (left, height) = self.parse_range((left, height))?;
+                continue;
+            }
+
+            let (op, is_implicit) = match self.current().and_then(|token| BinaryExpr::from_token(token.content())) {
+                Some(op) => (op, false),
+                None if self.at_implicit_operand() => (BinaryExpr::Multiply, true),
+                None => break,
+            };
+
+            if op.priority() < min_priority {
+                break;
+            }
+
+            if !is_implicit {
+                self.position += 1;
+            }
+            let min_priority = if op.is_right_associative() { op.priority() } else { op.priority() + 1 };
+            let (right, right_height) = self.nest(|parser| parser.parse_binary(min_priority))?;
+            let location = (left.location..=right.location).into();
+            (left, height) = Self::node(ExpressionContent::BinaryExpr(op, Box::new([left, right])), location, [height, right_height])?;
+        }
+
+        Ok((left, height))
+    }
+
+    /// Parses the rest of a range whose start has already been parsed
+    fn parse_range(&mut self, (start, start_height): Nested) -> Result<Nested, CompilationError> {
+        let inclusive = self.is_punct(Punct::DoubleDotEquals);
+        self.position += 1;
+
+        let (end, end_height) = self.nest(|parser| parser.parse_binary(BinaryExpr::Add.priority()))?;
+        let (step, step_height) = if self.is_keyword("step") {
+            self.position += 1;
+            let (step, height) = self.nest(|parser| parser.parse_binary(BinaryExpr::Add.priority()))?;
+            (Some(Box::new(step)), height)
+        }
+        else {
+            (None, 0)
+        };
+
+        let location = (start.location..=step.as_ref().map_or(end.location, |step| step.location)).into();
+        let content = ExpressionContent::Range { bounds: Box::new([start, end]), step, inclusive };
+        Self::node(content, location, [start_height, end_height, step_height])
+    }
+
+    fn parse_prefix(&mut self) -> Result<Nested, CompilationError> {
+        match self.current() {
+            Some(token) if token.content() == &TokenContent::Punct(Punct::Dash) => {
+                self.position += 1;
+                // -2^2 is -(2^2)
+                let (operand, height) = self.nest(|parser| parser.parse_binary(BinaryExpr::Power.priority()))?;
+                let location = (token.location..=operand.location).into();
+                Self::node(ExpressionContent::UnaryExpr(UnaryExpr::Negate, Box::new(operand)), location, [height])
+            },
+            Some(token) if matches!(token.content(), TokenContent::Punct(Punct::Bang | Punct::DoubleBang))
+                || token.content() == &TokenContent::Ident(String::from("not")) =>
+            {
+                self.position += 1;
+                // not a == b is not (a == b)
+                let (operand, height) = self.nest(|parser| parser.parse_binary(BinaryExpr::Equal.priority()))?;
+                let location = (token.location..=operand.location).into();
+                let mut nested = Self::node(ExpressionContent::UnaryExpr(UnaryExpr::Not, Box::new(operand)), location, [height])?;
+                if token.content() == &TokenContent::Punct(Punct::DoubleBang) {
+                    let (expr, height) = nested;
+                    nested = Self::node(ExpressionContent::UnaryExpr(UnaryExpr::Not, Box::new(expr)), location, [height])?;
+                }
+                Ok(nested)
+            },
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Nested, CompilationError> {
+        let (mut expr, mut height) = self.parse_primary()?;
+
+        while let Some(token) = self.current() {
+            let op = match token.content() {
+                TokenContent::Punct(Punct::Degree) => UnaryExpr::Degrees,
+                TokenContent::Punct(Punct::Bang) => UnaryExpr::Factorial,
+                TokenContent::Punct(Punct::DoubleBang) => UnaryExpr::DoubleFactorial,
+                TokenContent::Block(DelimiterType::Bracket, inner) => {
+                    self.position += 1;
+                    let (index, index_height) = self.parse_block(DelimiterType::Bracket, inner, token.location())?;
+                    let location = (expr.location..=token.location).into();
+                    (expr, height) = Self::node(ExpressionContent::Index(Box::new([expr, index])), location, [height, index_height])?;
+                    continue;
+                },
+                _ => break,
+            };
+
+            self.position += 1;
+            let location = (expr.location..=token.location).into();
+            (expr, height) = Self::node(ExpressionContent::UnaryExpr(op, Box::new(expr)), location, [height])?;
+        }
+
+        Ok((expr, height))
+    }
+
+    fn parse_primary(&mut self) -> Result<Nested, CompilationError> {
+        let token = match self.current() {
+            Some(token) => token,
+            None => return Err(self.syntax_error(&["expression"])),
+        };
+
+        let content = match token.content() {
+            TokenContent::Value(tokenize::Value::Number(n)) => {
+                self.position += 1;
+                ExpressionContent::Literal(SimpleValue::Number(Number::Exact(*n)))
+            },
+            TokenContent::Value(tokenize::Value::Str(string)) => {
+                self.position += 1;
+                ExpressionContent::Str(string.clone())
+            },
+            TokenContent::Value(tokenize::Value::Interpolated(parts)) => {
+                self.position += 1;
+                let (parts, heights): (Vec<_>, Vec<_>) = parts.iter()
+                    .map(|part| self.parse_str_part(part))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .unzip();
+                return Self::node(ExpressionContent::Interpolated(parts), token.location, heights);
+            },
+            TokenContent::Ident(ident) if ident == "if" => {
+                self.position += 1;
+                let (condition, condition_height) = self.nest(Self::parse_expression)?;
+                self.expect_keyword("then")?;
+                let (then, then_height) = self.nest(Self::parse_expression)?;
+                self.expect_keyword("else")?;
+                let (otherwise, otherwise_height) = self.nest(Self::parse_expression)?;
+                let location = (token.location..=otherwise.location).into();
+                let content = ExpressionContent::Conditional(Box::new([condition, then, otherwise]));
+                return Self::node(content, location, [condition_height, then_height, otherwise_height]);
+            },
+            TokenContent::Ident(ident) => {
+                self.position += 1;
+                if let Some(Token { content: TokenContent::Block(DelimiterType::Parenthases, args), location }) = self.current() {
+                    self.position += 1;
+                    let (args, heights): (Vec<_>, Vec<_>) = self.sub_parser(args, (location.end() - 1..location.end()).into())?
+                        .parse_args()?
+                        .into_iter()
+                        .unzip();
+                    return Self::node(ExpressionContent::ExecuteFn((ident.clone(), args)), (token.location..=*location).into(), heights);
+                }
+                ExpressionContent::Variable(ident.clone())
+            },
+            // Brackets holding a comma, or nothing, are a list. `[x,]` is a
+            // list with one element.
+            TokenContent::Block(DelimiterType::Bracket, inner)
+                if inner.is_empty() || inner.iter().any(|token| token.content() == &TokenContent::Punct(Punct::Comma)) =>
+            {
+                self.position += 1;
+                let end = token.location.end();
+                let (elements, heights): (Vec<_>, Vec<_>) = self.sub_parser(inner, (end - 1..end).into())?
+                    .parse_list()?
+                    .into_iter()
+                    .unzip();
+                return Self::node(ExpressionContent::List(elements), token.location, heights);
+            },
+            // Any kind of bracket can be used for grouping, e.g. `[(a + b) * c]`
+            TokenContent::Block(ty, inner) => {
+                self.position += 1;
+                let (inner, height) = self.parse_block(*ty, inner, token.location())?;
+                return Self::node(ExpressionContent::Parenthases(Box::new(inner)), token.location, [height]);
+            },
+            _ => return Err(self.syntax_error(&["expression"])),
         };
+
+        Ok((Expression::new(content, token.location), 1))
     }
 
-    let operators = [Vec::new(), Vec::new()];
+    /// Parses a part of an interpolated string, with the height of the
+    /// expression in it, or 0 for text
+    fn parse_str_part(&self, part: &StrPart) -> Result<(InterpolatedPart, usize), CompilationError> {
+        let (tokens, spec) = match part {
+            StrPart::Text(text) => return Ok((InterpolatedPart::Text(text.clone()), 0)),
+            StrPart::Hole(tokens, spec) => (tokens, spec),
+        };
+
+        let end = tokens.last().map_or_else(Location::default, |token| *token.location());
+        let mut parser = self.sub_parser(tokens, end)?;
+        let (expr, height) = parser.parse_expression()?;
+        if parser.is_done() {
+            Ok((InterpolatedPart::Hole(expr, *spec), height))
+        }
+        else {
+            Err(parser.syntax_error(&["}"]))
+        }
+    }
 
-    for token in stream.iter() {
+    /// Parses the elements of a list, which may be followed by a comma
+    fn parse_list(&mut self) -> Result<Vec<Nested>, CompilationError> {
+        let mut elements = Vec::new();
 
+        while !self.is_done() {
+            if self.is_punct(Punct::Comma) {
+                return Err(CompilationError::new(*self.current().unwrap().location(), CompilationErrorKind::TwoCommas));
+            }
+
+            elements.push(self.parse_expression()?);
+
+            if !self.is_done() && !self.eat_punct(Punct::Comma) {
+                return Err(self.syntax_error(&[",", "]"]));
+            }
+        }
+
+        Ok(elements)
     }
 
+    fn parse_args(&mut self) -> Result<Vec<Nested>, CompilationError> {
+        let mut args = Vec::new();
 
+        while !self.is_done() {
+            if self.is_punct(Punct::Comma) {
+                return Err(CompilationError::new(*self.current().unwrap().location(), CompilationErrorKind::TwoCommas));
+            }
 
-    todo!();
-}
+            args.push(self.parse_expression()?);
 
-// const ORDER: HashMap<Punct, u8> = HashMap::from([
+            if !self.is_done() {
+                if !self.eat_punct(Punct::Comma) {
+                    return Err(self.syntax_error(&[",", ")"]));
+                }
+                else if self.is_done() {
+                    return Err(self.syntax_error(&["expression"]));
+                }
+            }
+        }
 
-// ]);
+        Ok(args)
+    }
+}
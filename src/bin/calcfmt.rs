@@ -0,0 +1,36 @@
+use std::io::Read;
+
+use simple_calculator::formatter;
+
+/// Formats the files given as arguments, or stdin if there are none, and
+/// prints the result
+fn main() {
+  let paths = std::env::args().skip(1).collect::<Vec<_>>();
+
+  let sources = if paths.is_empty() {
+    let mut source = String::new();
+    std::io::stdin().read_to_string(&mut source).expect("Unable to read from stdin");
+    vec![(String::from("<stdin>"), source)]
+  }
+  else {
+    paths.into_iter()
+      .map(|path| {
+        let source = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+          eprintln!("{path}: {e}");
+          std::process::exit(1);
+        });
+        (path, source)
+      })
+      .collect()
+  };
+
+  for (path, source) in sources {
+    match formatter::format(&source) {
+      Ok(formatted) => print!("{formatted}"),
+      Err(e) => {
+        eprintln!("{path}: {e}");
+        std::process::exit(1);
+      }
+    }
+  }
+}
@@ -1,6 +1,44 @@
-use simple_calculator::tokenize;
+use std::io::Read;
+
+use simple_calculator::{ast, runtime::{AngleMode, Runtime}, tokenize};
 
 fn main() {
-  println!("{}", std::env::args().skip(1).collect::<String>());
-    println!("{:?}", tokenize::tokenize(std::env::args().skip(1).collect::<String>().chars()))
+  let mut args = std::env::args().skip(1).peekable();
+  let angle_mode = match args.peek().map(String::as_str) {
+    Some("--degrees") => Some(AngleMode::Degrees),
+    Some("--gradians") => Some(AngleMode::Gradians),
+    Some("--radians") => Some(AngleMode::Radians),
+    _ => None,
+  };
+  if angle_mode.is_some() {
+    args.next();
+  }
+
+  let mut source = args.collect::<Vec<_>>().join(" ");
+  if source.is_empty() {
+    std::io::stdin().read_to_string(&mut source).expect("Unable to read from stdin");
+  }
+
+  let code = match tokenize::tokenize(source.chars()).and_then(ast::parse) {
+    Ok(code) => code,
+    Err(e) => {
+      eprintln!("{e}");
+      std::process::exit(1);
+    }
+  };
+
+  let mut runtime = Runtime::new();
+  if let Some(angle_mode) = angle_mode {
+    runtime.set_angle_mode(angle_mode);
+  }
+  for object in code.iter() {
+    match runtime.exec(object) {
+      Ok(Some(value)) => println!("{value}"),
+      Ok(None) => {},
+      Err(e) => {
+        eprintln!("{}", e.to_string());
+        std::process::exit(1);
+      }
+    }
+  }
 }
@@ -3,9 +3,6 @@ use std::{error::Error, fmt::{self, Display, Formatter}};
 use crate::Location;
 use crate::tokenize::Token;
 
-#[derive(Debug, Copy, Clone)]
-struct UnknownLocation;
-
 #[derive(Debug, Clone)]
 pub struct CompilationError {
     location: Location,
@@ -30,8 +27,37 @@ pub enum CompilationErrorKind {
     /// The opening and closing delimiters do not match. E.g. `(foo}`
     MismatchedDelimiter,
 
+    /// Brackets or expressions nested more than `MAX_DEPTH` deep. E.g.
+    /// `((((...1))))` or `1 + 1 + ... + 1`
+    TooDeeplyNested,
+
     TwoCommas,
 
+    /// A string literal with no closing quote
+    UnterminatedString,
+
+    /// A `{` in a string literal without a matching `}`
+    UnterminatedInterpolation,
+
+    /// A `/*` without a matching `*/`
+    UnterminatedComment,
+
+    /// A `//` comment after an operand that starts with another one, which
+    /// is likely meant to be floor division, written `div`. E.g. `7 // 2`
+    FloorDivisionComment,
+
+    /// A format spec in an interpolated string with a width or number of
+    /// decimal places over the limit. E.g. `"{x:.100000}"`
+    FormatSpecTooLarge,
+
+    /// A backslash in a string literal followed by a character that can't
+    /// be escaped. E.g. `"\q"`
+    InvalidEscape,
+
+    /// A part of an expression made only of literals that can't be
+    /// evaluated, with the reason. E.g. `1 / 0`
+    ConstantError(String),
+
     /// The compiler has no idea what's going on
     SyntaxError {
         expected: Vec<String>,
@@ -45,6 +71,14 @@ impl CompilationError {
     pub fn new(location: Location, kind: CompilationErrorKind) -> Self {
         Self { location, kind }
     }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    pub fn kind(&self) -> &CompilationErrorKind {
+        &self.kind
+    }
 }
 
 
@@ -0,0 +1,174 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::ast::{SimpleValue, Value};
+use crate::number::Number;
+
+/// How a value is written in an interpolated string or by `format`, e.g. the
+/// `>8.3` in `"{x:>8.3}"`. The parts are, in order and all optional: an
+/// alignment of `<`, `>` or `^`, `0` to pad numbers with zeros, a minimum
+/// width, a number of decimal places, and `f` to write numbers as fractions
+/// or `d` as decimals.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FormatSpec {
+    align: Option<Align>,
+    /// Pad numbers with zeros after their sign, instead of with spaces
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    mode: Option<NumberMode>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NumberMode {
+    Fraction,
+    Decimal,
+}
+
+/// Why a format spec couldn't be read
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FormatSpecError {
+    /// The text isn't a format spec at all
+    Invalid,
+    /// A width or number of decimal places over the limit
+    TooLarge,
+}
+
+/// The largest width and number of decimal places allowed, so that one
+/// format string can't use up all memory
+const MAX_WIDTH: usize = 1000;
+const MAX_PRECISION: usize = 1000;
+
+impl FormatSpec {
+    pub fn apply(&self, value: &Value) -> String {
+        let text = self.write(value);
+
+        if let (true, Value::Simple(SimpleValue::Number(_))) = (self.zero, value) {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            let width = self.width.saturating_sub(sign.len());
+            return format!("{sign}{digits:0>width$}");
+        }
+
+        // Numbers line up on the right by default, everything else on the left
+        let align = self.align.unwrap_or(match value {
+            Value::Simple(SimpleValue::Number(_)) => Align::Right,
+            _ => Align::Left,
+        });
+        let padding = self.width.saturating_sub(text.chars().count());
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+
+        format!("{}{text}{}", " ".repeat(before), " ".repeat(after))
+    }
+
+    /// Writes a value without any padding
+    fn write(&self, value: &Value) -> String {
+        match value {
+            Value::Simple(SimpleValue::Number(n)) => self.write_number(n),
+            Value::List(items) => format!("[{}]", items.iter().map(|item| self.write(item)).collect::<Vec<_>>().join(", ")),
+            value => value.to_string(),
+        }
+    }
+
+    fn write_number(&self, n: &Number) -> String {
+        match (self.mode, self.precision) {
+            (Some(NumberMode::Fraction), _) | (None, None) => n.to_string(),
+            (_, Some(digits)) => n.to_decimal(digits),
+            (Some(NumberMode::Decimal), None) => n.to_f64().to_string(),
+        }
+    }
+}
+
+impl Display for FormatSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.align {
+            Some(Align::Left) => write!(f, "<")?,
+            Some(Align::Right) => write!(f, ">")?,
+            Some(Align::Center) => write!(f, "^")?,
+            None => {},
+        }
+        if self.zero {
+            write!(f, "0")?;
+        }
+        if self.width > 0 {
+            write!(f, "{}", self.width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{precision}")?;
+        }
+        match self.mode {
+            Some(NumberMode::Fraction) => write!(f, "f"),
+            Some(NumberMode::Decimal) => write!(f, "d"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = FormatSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = FormatSpec::default();
+        let mut rest = s;
+
+        spec.align = match rest.chars().next() {
+            Some('<') => Some(Align::Left),
+            Some('>') => Some(Align::Right),
+            Some('^') => Some(Align::Center),
+            _ => None,
+        };
+        if spec.align.is_some() {
+            rest = &rest[1..];
+        }
+
+        if let Some(after_zero) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = after_zero;
+        }
+
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        // Digits that don't fit in a `usize` are certainly over the limit
+        let number = |s: &str| s.parse::<usize>().map_err(|_| FormatSpecError::TooLarge);
+
+        let width = digits(rest);
+        if width > 0 {
+            spec.width = number(&rest[..width])?;
+            rest = &rest[width..];
+        }
+
+        if let Some(after_point) = rest.strip_prefix('.') {
+            let precision = digits(after_point);
+            if precision == 0 {
+                return Err(FormatSpecError::Invalid);
+            }
+            spec.precision = Some(number(&after_point[..precision])?);
+            rest = &after_point[precision..];
+        }
+
+        spec.mode = match rest {
+            "f" => Some(NumberMode::Fraction),
+            "d" => Some(NumberMode::Decimal),
+            "" => None,
+            _ => return Err(FormatSpecError::Invalid),
+        };
+
+        if spec.width > MAX_WIDTH || spec.precision.is_some_and(|precision| precision > MAX_PRECISION) {
+            return Err(FormatSpecError::TooLarge);
+        }
+
+        Ok(spec)
+    }
+}
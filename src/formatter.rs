@@ -0,0 +1,352 @@
+use num_rational::Rational64;
+use num_traits::{One, Signed, Zero};
+
+use crate::ast::{self, BinaryExpr, Clause, CodeObject, CodeObjectContent, Expression, ExpressionContent, InterpolatedPart, SimpleValue};
+use crate::number::{Irrational, Number, Symbolic};
+use crate::error::CompilationError;
+use crate::tokenize::{self, TokenContent, lossless::{self, Trivia, TriviaContent}};
+use crate::Location;
+
+/// Reprints calculator source in a canonical style: one statement per line,
+/// single spaces around binary operators, and only the parentheses needed
+/// to keep the meaning of the code. Comments are kept, though any inside a
+/// statement are moved to the end of it.
+pub fn format(source: &str) -> Result<String, CompilationError> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let code = ast::parse(tokenize::tokenize(chars.iter().copied())?)?;
+
+    let mut comments = lossless::tokenize_lossless(source)?
+        .into_iter()
+        .flat_map(|token| token.leading().iter().chain(token.trailing()).cloned().collect::<Vec<_>>())
+        .filter(|trivia| !matches!(trivia.content(), TriviaContent::Whitespace(_)))
+        .peekable();
+
+    let printer = Printer::new(Some(&chars));
+    let mut output = Output { text: String::new(), chars: &chars, position: 0, after_line_comment: false };
+
+    for statement in &code {
+        let location = statement.location();
+        while let Some(comment) = comments.next_if(|comment| comment.location().begin() < location.begin()) {
+            output.separate(comment.location(), false);
+            output.push_comment(&comment);
+        }
+
+        output.separate(location, true);
+        output.push(&printer.statement(statement), location);
+
+        while let Some(comment) = comments.next_if(|comment| comment.location().begin() < location.end()) {
+            output.text.push(if output.after_line_comment { '\n' } else { ' ' });
+            output.push_comment(&comment);
+        }
+    }
+
+    for comment in comments {
+        output.separate(comment.location(), false);
+        output.push_comment(&comment);
+    }
+
+    if !output.text.is_empty() {
+        output.text.push('\n');
+    }
+    Ok(output.text)
+}
+
+/// The formatted code written so far
+struct Output<'a> {
+    text: String,
+    chars: &'a [char],
+    /// The end of the last thing written, in the original source
+    position: usize,
+    /// Whether the current line ends with a line comment, so nothing else
+    /// can be written on it
+    after_line_comment: bool,
+}
+
+impl Output<'_> {
+    /// Separates the last thing written from the next, which starts at
+    /// `next`, by keeping them on one line or with at most one blank line
+    /// between them, as they were in the source
+    fn separate(&mut self, next: &Location, is_statement: bool) {
+        if self.text.is_empty() {
+            return;
+        }
+
+        let gap = &self.chars[self.position.min(next.begin())..next.begin()];
+        let line_breaks = gap.iter().filter(|c| **c == '\n').count();
+        match line_breaks {
+            // Statements that shared a line with `;` get a line each
+            0 if is_statement || self.after_line_comment => self.text.push('\n'),
+            0 => self.text.push(' '),
+            1 => self.text.push('\n'),
+            _ => self.text.push_str("\n\n"),
+        }
+    }
+
+    fn push(&mut self, text: &str, location: &Location) {
+        self.text.push_str(text);
+        self.position = self.position.max(location.end());
+        self.after_line_comment = false;
+    }
+
+    fn push_comment(&mut self, comment: &Trivia) {
+        self.push(comment.content().text(), comment.location());
+        self.after_line_comment = matches!(comment.content(), TriviaContent::LineComment(_));
+    }
+}
+
+/// Writes a literal that has no source text, such as one made by folding
+/// constants. Rationals are written as decimals where that is exact, so
+/// that they are read back as a single literal, and irrational numbers in
+/// terms of `sqrt`, `pi` and `e`.
+fn literal_value(value: &SimpleValue) -> (String, u8) {
+    let n = match value {
+        SimpleValue::Number(Number::Exact(n)) => n,
+        SimpleValue::Number(Number::Symbolic(s)) => return Printer::new(None).unparenthesised(&symbolic(s)),
+        value => {
+            let text = value.to_string();
+            let strength = if text.starts_with('-') {
+                PREFIX
+            }
+            else if text.contains('/') {
+                binary_strength(&BinaryExpr::Divide)
+            }
+            else {
+                POSTFIX
+            };
+            return (text, strength);
+        },
+    };
+
+    let digits = (0..19).find(|digits| 10i64.checked_pow(*digits).is_some_and(|scale| scale % n.denom() == 0));
+    let text = match digits {
+        Some(digits) => Number::Exact(*n).to_decimal(digits as usize).trim_start_matches('-').to_owned(),
+        None => format!("{} / {}", n.numer().unsigned_abs(), n.denom()),
+    };
+
+    match (n.is_negative(), digits.is_some()) {
+        (false, true) => (text, POSTFIX),
+        (false, false) => (text, binary_strength(&BinaryExpr::Divide)),
+        (true, true) => (format!("-{text}"), PREFIX),
+        (true, false) => (format!("-({text})"), PREFIX),
+    }
+}
+
+/// An expression for `rational + coefficient * irrational`, leaving out the
+/// parts that make no difference
+fn symbolic(s: &Symbolic) -> Expression {
+    let location = Location::default();
+    let number = |n| Expression::new(ExpressionContent::Literal(SimpleValue::Number(Number::Exact(n))), location);
+
+    let irrational = Expression::new(match s.irrational() {
+        Irrational::Pi => ExpressionContent::Variable(String::from("pi")),
+        Irrational::E => ExpressionContent::Variable(String::from("e")),
+        Irrational::Sqrt(n) => ExpressionContent::ExecuteFn((String::from("sqrt"), vec![number(Rational64::from_integer(n))])),
+    }, location);
+
+    // `1 - sqrt(2)` rather than `1 + -1 * sqrt(2)`
+    let subtract = s.coefficient().is_negative() && !s.rational().is_zero() && *s.coefficient().numer() != i64::MIN;
+    let coefficient = if subtract { -s.coefficient() } else { s.coefficient() };
+    let term = if coefficient.is_one() {
+        irrational
+    }
+    else if (-coefficient).is_one() {
+        Expression::new(ExpressionContent::UnaryExpr(ast::UnaryExpr::Negate, Box::new(irrational)), location)
+    }
+    else {
+        Expression::new(ExpressionContent::BinaryExpr(BinaryExpr::Multiply, Box::new([number(coefficient), irrational])), location)
+    };
+
+    if s.rational().is_zero() {
+        return term;
+    }
+    let op = if subtract { BinaryExpr::Subtract } else { BinaryExpr::Add };
+    Expression::new(ExpressionContent::BinaryExpr(op, Box::new([number(s.rational()), term])), location)
+}
+
+/// Escapes a string so it can be written in a string literal
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for c in string.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' | '"' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Binding strength used to decide where parentheses are needed, with room
+/// between the priorities of binary operators for `not` and ranges. `not`
+/// binds looser than comparisons, as `not a == b` is `not (a == b)`, so
+/// `(not a) == b` keeps its parentheses.
+pub(crate) const CONDITIONAL: u8 = 0;
+pub(crate) const RANGE: u8 = 7;
+pub(crate) const NOT: u8 = 5;
+pub(crate) const PREFIX: u8 = 12;
+pub(crate) const POSTFIX: u8 = 14;
+
+pub(crate) fn binary_strength(op: &BinaryExpr) -> u8 {
+    op.priority() * 2
+}
+
+/// Prints code with the least parentheses needed. With the source of the
+/// code, literals are written exactly as they were.
+pub struct Printer<'a> {
+    source: Option<&'a [char]>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(source: Option<&'a [char]>) -> Self {
+        Self { source }
+    }
+
+    pub fn statement(&self, statement: &CodeObject) -> String {
+        match statement.content() {
+            CodeObjectContent::ExecuteFn((f, args)) => self.call(f, args),
+            CodeObjectContent::Expression(expr) => self.expression(expr),
+            CodeObjectContent::Assign(ident, expr) => format!("{ident} = {}", self.expression(expr)),
+            CodeObjectContent::DefineFn(ident, params, clauses) => {
+                format!("{ident}({}) = {}", params.join(", "), self.clauses(clauses))
+            },
+        }
+    }
+
+    fn clauses(&self, clauses: &[Clause]) -> String {
+        clauses.iter()
+            .map(|(body, guard)| match guard {
+                Some(guard) => format!("{} when {}", self.expression(body), self.expression(guard)),
+                None if clauses.len() > 1 => format!("{} otherwise", self.expression(body)),
+                None => self.expression(body),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn expression(&self, expr: &Expression) -> String {
+        self.operand(expr, CONDITIONAL)
+    }
+
+    /// Prints an expression that must bind at least as strongly as `min`,
+    /// adding parentheses if it doesn't
+    fn operand(&self, expr: &Expression, min: u8) -> String {
+        // Parentheses in the source are dropped, and added back if needed
+        if let ExpressionContent::Parenthases(inner) = expr.content() {
+            return self.operand(inner, min);
+        }
+
+        let (text, strength) = self.unparenthesised(expr);
+        if strength < min {
+            format!("({text})")
+        }
+        else {
+            text
+        }
+    }
+
+    fn unparenthesised(&self, expr: &Expression) -> (String, u8) {
+        use ExpressionContent::*;
+
+        match expr.content() {
+            ExecuteFn((f, args)) => (self.call(f, args), POSTFIX),
+            Parenthases(inner) => self.unparenthesised(inner),
+            Literal(value) => match self.literal(expr) {
+                Some(text) => (text, POSTFIX),
+                None => literal_value(value),
+            },
+            Str(string) => (self.literal(expr).unwrap_or_else(|| format!("\"{}\"", escape(string))), POSTFIX),
+            Interpolated(parts) => (self.literal(expr).unwrap_or_else(|| self.interpolated(parts)), POSTFIX),
+            Variable(ident) => (ident.clone(), POSTFIX),
+            UnaryExpr(op, operand) => match op {
+                ast::UnaryExpr::Negate => (format!("-{}", self.operand(operand, PREFIX)), PREFIX),
+                ast::UnaryExpr::Not => (format!("not {}", self.operand(operand, binary_strength(&ast::BinaryExpr::Equal))), NOT),
+                op => {
+                    let mut operand = self.operand(operand, POSTFIX);
+                    // `(5!)!` would otherwise become the double factorial `5!!`
+                    if operand.ends_with('!') && op.symbol().starts_with('!') {
+                        operand = format!("({operand})");
+                    }
+                    (format!("{operand}{}", op.symbol()), POSTFIX)
+                },
+            },
+            BinaryExpr(op, args) => {
+                let strength = binary_strength(op);
+                let (left, right) = if op.is_right_associative() { (strength + 1, strength) } else { (strength, strength + 1) };
+                (format!("{} {} {}", self.operand(&args[0], left), op.symbol(), self.operand(&args[1], right)), strength)
+            },
+            Conditional(branches) => {
+                let [condition, then, otherwise] = &**branches;
+                (format!("if {} then {} else {}", self.expression(condition), self.expression(then), self.expression(otherwise)), CONDITIONAL)
+            },
+            List(elements) => {
+                let elements = elements.iter().map(|element| self.expression(element)).collect::<Vec<_>>();
+                // A list of one element needs a comma to not be a grouping
+                let trailing = if elements.len() == 1 { "," } else { "" };
+                (format!("[{}{trailing}]", elements.join(", ")), POSTFIX)
+            },
+            Index(args) => (format!("{}[{}]", self.operand(&args[0], POSTFIX), self.expression(&args[1])), POSTFIX),
+            Range { bounds, step, inclusive } => {
+                let add = binary_strength(&ast::BinaryExpr::Add);
+                let mut text = format!(
+                    "{}{}{}",
+                    self.operand(&bounds[0], add),
+                    if *inclusive { "..=" } else { ".." },
+                    self.operand(&bounds[1], add),
+                );
+                if let Some(step) = step {
+                    text.push_str(&format!(" step {}", self.operand(step, add)));
+                }
+                (text, RANGE)
+            },
+        }
+    }
+
+    fn interpolated(&self, parts: &[InterpolatedPart]) -> String {
+        let parts = parts.iter()
+            .map(|part| match part {
+                InterpolatedPart::Text(text) => escape(text),
+                InterpolatedPart::Hole(expr, spec) => {
+                    let mut hole = self.expression(expr);
+                    // `{3}` would be read as a placeholder for `format`
+                    if hole.chars().all(|c| c.is_ascii_digit()) {
+                        hole = format!("({hole})");
+                    }
+                    match spec {
+                        Some(spec) => format!("{{{hole}:{spec}}}"),
+                        None => format!("{{{hole}}}"),
+                    }
+                },
+            })
+            .collect::<String>();
+        format!("\"{parts}\"")
+    }
+
+    fn call(&self, f: &str, args: &[Expression]) -> String {
+        format!("{f}({})", args.iter().map(|arg| self.expression(arg)).collect::<Vec<_>>().join(", "))
+    }
+
+    /// The source text of a literal, if the source is known and the literal
+    /// is written there, rather than made by folding constants
+    fn literal(&self, expr: &Expression) -> Option<String> {
+        let text = self.source?[expr.location().begin()..expr.location().end()].iter().collect::<String>();
+        let tokens = tokenize::tokenize(text.chars()).ok()?;
+        let value = match &tokens[..] {
+            [token, end] if end.content() == &TokenContent::EOF => token.content(),
+            _ => return None,
+        };
+
+        let is_literal = match (expr.content(), value) {
+            (ExpressionContent::Literal(SimpleValue::Number(Number::Exact(n))), TokenContent::Value(tokenize::Value::Number(m))) => n == m,
+            (ExpressionContent::Str(string), TokenContent::Value(tokenize::Value::Str(text))) => string == text,
+            (ExpressionContent::Interpolated(_), TokenContent::Value(tokenize::Value::Interpolated(_))) => true,
+            _ => false,
+        };
+        is_literal.then_some(text)
+    }
+}
@@ -1,11 +1,21 @@
-use std::collections::Bound;
 use std::fmt::{self, Formatter, Display};
-use std::ops::{Range, RangeBounds, RangeInclusive};
+use std::ops::{Range, RangeInclusive};
 
 pub mod tokenize;
 pub mod ast;
 pub mod error;
+pub mod format_spec;
+pub mod formatter;
+pub mod number;
+pub mod optimize;
+pub mod render;
 pub mod runtime;
+pub mod visit;
+
+/// How deeply expressions can be nested inside each other, e.g. with brackets
+/// or chains of operators. Deeper code is rejected when it is compiled, as
+/// everything that walks a syntax tree does so recursively.
+pub const MAX_DEPTH: usize = 256;
 
 // Why not just use Range? It doesn't implement Copy, because this is not what it's meant for
 // (see https://www.reddit.com/r/rust/comments/rrgxr0/a_critique_of_rusts_range_types/?utm_source=share&utm_medium=web2x&context=3)
@@ -24,6 +34,10 @@ impl Location {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     /// The (exclusive) end of the location
     pub fn end(&self) -> usize {
         self.begin + self.len
@@ -104,6 +118,14 @@ impl<T> LocatableContent<T> {
         &self.content
     }
 
+    pub fn content_mut(&mut self) -> &mut T {
+        &mut self.content
+    }
+
+    pub fn into_content(self) -> T {
+        self.content
+    }
+
     pub fn new(content: T, location: Location) -> Self {
         Self { content, location }
     }
@@ -0,0 +1,712 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::result;
+use num_bigint::BigInt;
+use num_rational::{BigRational, Rational64};
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, One, Pow, Signed, ToPrimitive, Zero};
+
+/// A real number. Wherever possible numbers are kept exact, either as a
+/// rational or as a rational combination of a single irrational, and only
+/// become approximate when there is no exact way to represent the result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Exact(Rational64),
+    /// An exact rational that does not fit in `Exact`
+    Big(BigRational),
+    Symbolic(Symbolic),
+    Approx(f64),
+}
+
+/// How large exact rationals are allowed to become
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Precision {
+    /// Rationals must fit in 64 bits, anything larger is an overflow
+    Fixed,
+    /// Rationals can be arbitrarily large, as long as their numerator and
+    /// denominator fit in `max_bits` bits between them
+    Big { max_bits: u64 },
+}
+
+/// `rational + coefficient * irrational`. The coefficient is never zero,
+/// if it would be the number is `Number::Exact` instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Symbolic {
+    rational: Rational64,
+    coefficient: Rational64,
+    irrational: Irrational,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Irrational {
+    Pi,
+    E,
+    /// The square root of a square-free integer greater than 1
+    Sqrt(i64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NumberError {
+    /// The result does not fit in a 64 bit rational
+    Overflow,
+    DivisionByZero,
+    /// The result is not a real number, e.g. `sqrt(-1)` or `tan(pi/2)`
+    Undefined,
+    /// A factorial of something other than a non-negative integer
+    InvalidFactorial,
+    /// The result needs more bits than `Precision::Big` allows
+    TooLarge { max_bits: u64 },
+}
+
+impl Display for NumberError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use NumberError::*;
+
+        match self {
+            Overflow => write!(f, "Number too large"),
+            DivisionByZero => write!(f, "Division by zero"),
+            Undefined => write!(f, "Result is undefined"),
+            InvalidFactorial => write!(f, "Factorial is only defined for non-negative integers"),
+            TooLarge { max_bits } => write!(f, "Number needs more than {max_bits} bits"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, NumberError>;
+
+/// The exact parts of a number, `(rational, coefficient, irrational)`
+type Parts = (Rational64, Rational64, Option<Irrational>);
+
+impl Precision {
+    /// The error for a number too large for this precision
+    fn too_large(&self) -> NumberError {
+        match self {
+            Precision::Fixed => NumberError::Overflow,
+            Precision::Big { max_bits } => NumberError::TooLarge { max_bits: *max_bits },
+        }
+    }
+}
+
+impl Symbolic {
+    pub fn rational(&self) -> Rational64 {
+        self.rational
+    }
+
+    pub fn coefficient(&self) -> Rational64 {
+        self.coefficient
+    }
+
+    pub fn irrational(&self) -> Irrational {
+        self.irrational
+    }
+}
+
+impl Irrational {
+    pub fn to_f64(&self) -> f64 {
+        use Irrational::*;
+
+        match self {
+            Pi => std::f64::consts::PI,
+            E => std::f64::consts::E,
+            Sqrt(n) => (*n as f64).sqrt(),
+        }
+    }
+
+    /// The square of this irrational, if it is rational
+    fn square(&self) -> Option<Rational64> {
+        match self {
+            Irrational::Sqrt(n) => Some(Rational64::from_integer(*n)),
+            _ => None,
+        }
+    }
+}
+
+impl Number {
+    pub fn integer(n: i64) -> Self {
+        Self::Exact(Rational64::from_integer(n))
+    }
+
+    /// `rational + coefficient * irrational`
+    pub fn symbolic(rational: Rational64, coefficient: Rational64, irrational: Irrational) -> Self {
+        if coefficient.is_zero() {
+            Self::Exact(rational)
+        }
+        else {
+            Self::Symbolic(Symbolic { rational, coefficient, irrational })
+        }
+    }
+
+    pub fn pi() -> Self {
+        Self::symbolic(Rational64::zero(), Rational64::one(), Irrational::Pi)
+    }
+
+    pub fn e() -> Self {
+        Self::symbolic(Rational64::zero(), Rational64::one(), Irrational::E)
+    }
+
+    /// The golden ratio, `(1 + sqrt(5)) / 2`
+    pub fn phi() -> Self {
+        let half = Rational64::new(1, 2);
+        Self::symbolic(half, half, Irrational::Sqrt(5))
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Self::Approx(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Self::Exact(n) => n.is_zero(),
+            Self::Big(_) | Self::Symbolic(_) => false,
+            Self::Approx(n) => *n == 0.0,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Self::Exact(n) => rational_to_f64(n),
+            Self::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Self::Symbolic(s) => rational_to_f64(&s.rational) + rational_to_f64(&s.coefficient) * s.irrational.to_f64(),
+            Self::Approx(n) => *n,
+        }
+    }
+
+    /// Writes the number with exactly `digits` digits after the decimal
+    /// point. Rationals are rounded exactly rather than through `f64`.
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let n = match self.as_big() {
+            Some(n) => n,
+            None => return format!("{:.digits$}", self.to_f64()),
+        };
+
+        let scaled = (n * BigRational::from_integer(BigInt::from(10).pow(digits as u32))).round().to_integer();
+        let sign = if scaled.is_negative() { "-" } else { "" };
+        let scaled = format!("{:0>width$}", scaled.abs(), width = digits + 1);
+        let (whole, fraction) = scaled.split_at(scaled.len() - digits);
+
+        if digits == 0 {
+            format!("{sign}{whole}")
+        }
+        else {
+            format!("{sign}{whole}.{fraction}")
+        }
+    }
+
+    /// If this number is a rational multiple of pi, returns that multiple
+    pub fn as_pi_multiple(&self) -> Option<Rational64> {
+        match self {
+            Self::Exact(n) if n.is_zero() => Some(*n),
+            Self::Symbolic(Symbolic { rational, coefficient, irrational: Irrational::Pi }) if rational.is_zero()
+                => Some(*coefficient),
+            _ => None,
+        }
+    }
+
+    /// Compares two numbers by value, returning `None` if either is NaN
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match self.sub(other) {
+            Ok(Self::Exact(difference)) => Some(difference.cmp(&Rational64::zero())),
+            Ok(Self::Big(difference)) => Some(difference.cmp(&BigRational::zero())),
+            // A symbolic number is never zero, so its sign can safely be
+            // found from its approximation
+            Ok(difference @ Self::Symbolic(_)) => difference.to_f64().partial_cmp(&0.0),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    /// The number of bits needed to store this number exactly, if it is a
+    /// rational
+    pub fn bits(&self) -> Option<u64> {
+        let bits = |n: i64| u64::from(64 - n.unsigned_abs().leading_zeros());
+
+        match self {
+            Self::Exact(n) => Some(bits(*n.numer()) + bits(*n.denom())),
+            Self::Big(n) => Some(n.numer().bits() + n.denom().bits()),
+            _ => None,
+        }
+    }
+
+    /// Fails if this number is larger than `precision` allows
+    pub fn check_size(self, precision: Precision) -> Result<Self> {
+        match precision {
+            Precision::Fixed if matches!(self, Self::Big(_)) => Err(NumberError::Overflow),
+            Precision::Big { max_bits } if self.bits().is_some_and(|bits| bits > max_bits)
+                => Err(NumberError::TooLarge { max_bits }),
+            _ => Ok(self),
+        }
+    }
+
+    fn as_big(&self) -> Option<BigRational> {
+        match self {
+            Self::Exact(n) => Some(BigRational::new_raw(BigInt::from(*n.numer()), BigInt::from(*n.denom()))),
+            Self::Big(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    fn from_big(n: BigRational) -> Self {
+        match (n.numer().to_i64(), n.denom().to_i64()) {
+            (Some(numer), Some(denom)) => Self::Exact(Rational64::new_raw(numer, denom)),
+            _ => Self::Big(n),
+        }
+    }
+
+    /// Applies an operation to two rationals, using `small` unless its result
+    /// does not fit in 64 bits, in which case `big` is used
+    fn rational_op(
+        &self,
+        other: &Self,
+        small: impl FnOnce(&Rational64, &Rational64) -> Option<Rational64>,
+        big: impl FnOnce(BigRational, BigRational) -> BigRational,
+    ) -> Option<Self> {
+        if let (Self::Exact(a), Self::Exact(b)) = (self, other) {
+            if let Some(result) = small(a, b) {
+                return Some(Self::Exact(result));
+            }
+        }
+
+        Some(Self::from_big(big(self.as_big()?, other.as_big()?)))
+    }
+
+    fn parts(&self) -> Option<Parts> {
+        match self {
+            Self::Exact(n) => Some((*n, Rational64::zero(), None)),
+            Self::Symbolic(s) => Some((s.rational, s.coefficient, Some(s.irrational))),
+            Self::Big(_) | Self::Approx(_) => None,
+        }
+    }
+
+    fn from_parts((rational, coefficient, irrational): Parts) -> Self {
+        match irrational {
+            Some(irrational) => Self::symbolic(rational, coefficient, irrational),
+            None => Self::Exact(rational),
+        }
+    }
+
+    /// Combines two irrationals if they are compatible, i.e. if they are the
+    /// same irrational or at least one of them is absent
+    fn common_irrational(a: Option<Irrational>, b: Option<Irrational>) -> Option<Option<Irrational>> {
+        match (a, b) {
+            (Some(a), Some(b)) if a != b => None,
+            (a, b) => Some(a.or(b)),
+        }
+    }
+
+    pub fn neg(&self) -> Result<Self> {
+        Self::integer(0).sub(self)
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self> {
+        if let Some(result) = self.rational_op(other, |a, b| a.checked_add(b), |a, b| a + b) {
+            return Ok(result);
+        }
+        else if let (Some((a, b, k)), Some((c, d, l))) = (self.parts(), other.parts()) {
+            if let Some(irrational) = Self::common_irrational(k, l) {
+                return Ok(Self::from_parts((checked(a.checked_add(&c))?, checked(b.checked_add(&d))?, irrational)));
+            }
+        }
+
+        Ok(Self::Approx(self.to_f64() + other.to_f64()))
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self> {
+        if let Some(result) = self.rational_op(other, |a, b| a.checked_sub(b), |a, b| a - b) {
+            return Ok(result);
+        }
+        else if let (Some((a, b, k)), Some((c, d, l))) = (self.parts(), other.parts()) {
+            if let Some(irrational) = Self::common_irrational(k, l) {
+                return Ok(Self::from_parts((checked(a.checked_sub(&c))?, checked(b.checked_sub(&d))?, irrational)));
+            }
+        }
+
+        Ok(Self::Approx(self.to_f64() - other.to_f64()))
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self> {
+        if let Some(result) = self.rational_op(other, |a, b| a.checked_mul(b), |a, b| a * b) {
+            return Ok(result);
+        }
+        else if let (Some((a, b, k)), Some((c, d, l))) = (self.parts(), other.parts()) {
+            // (a + bk)(c + dl) = ac + adl + bck + bdkl
+            if b.is_zero() || d.is_zero() {
+                let coefficient = checked(checked(a.checked_mul(&d))?.checked_add(&checked(b.checked_mul(&c))?))?;
+                return Ok(Self::from_parts((checked(a.checked_mul(&c))?, coefficient, k.or(l))));
+            }
+            else if let Some(square) = k.filter(|k| Some(*k) == l).and_then(|k| k.square()) {
+                let rational = checked(checked(a.checked_mul(&c))?.checked_add(&checked(checked(b.checked_mul(&d))?.checked_mul(&square))?))?;
+                let coefficient = checked(checked(a.checked_mul(&d))?.checked_add(&checked(b.checked_mul(&c))?))?;
+                return Ok(Self::from_parts((rational, coefficient, k)));
+            }
+        }
+
+        Ok(Self::Approx(self.to_f64() * other.to_f64()))
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self> {
+        if other.is_exact() && other.is_zero() {
+            return Err(NumberError::DivisionByZero);
+        }
+        else if let Some(result) = self.rational_op(other, |a, b| a.checked_div(b), |a, b| a / b) {
+            return Ok(result);
+        }
+        else if let (Some((a, b, k)), Some((c, d, l))) = (self.parts(), other.parts()) {
+            if d.is_zero() {
+                if c.is_zero() {
+                    return Err(NumberError::DivisionByZero);
+                }
+                let c = checked_recip(c)?;
+                return Ok(Self::from_parts((checked(a.checked_mul(&c))?, checked(b.checked_mul(&c))?, k)));
+            }
+            else if let Some(square) = l.and_then(|l| l.square()) {
+                // Multiply the numerator and denominator by the conjugate of
+                // the denominator, (c + dl)(c - dl) = c^2 - d^2 * l^2
+                let conjugate = Self::from_parts((c, checked(Rational64::zero().checked_sub(&d))?, l));
+                let denominator = checked(checked(c.checked_mul(&c))?.checked_sub(&checked(checked(d.checked_mul(&d))?.checked_mul(&square))?))?;
+                return self.mul(&conjugate)?.div(&Self::Exact(denominator));
+            }
+            else if a.is_zero() && c.is_zero() && k == l {
+                return Ok(Self::Exact(checked(b.checked_mul(&checked_recip(d)?))?));
+            }
+        }
+
+        Ok(Self::Approx(self.to_f64() / other.to_f64()))
+    }
+
+    /// Division rounded down to the nearest integer
+    pub fn floor_div(&self, other: &Self) -> Result<Self> {
+        if other.is_zero() {
+            return Err(NumberError::DivisionByZero);
+        }
+
+        match self.div(other)? {
+            Self::Exact(quotient) => Ok(Self::Exact(floor(&quotient))),
+            Self::Big(quotient) => Ok(Self::from_big(quotient.floor())),
+            quotient => Ok(Self::Approx(quotient.to_f64().floor())),
+        }
+    }
+
+    /// Floored modulo, `self - other * floor(self / other)`, which has the
+    /// same sign as `other`
+    pub fn modulo(&self, other: &Self) -> Result<Self> {
+        self.sub(&other.mul(&self.floor_div(other)?)?)
+    }
+
+    /// Truncated remainder, `self - other * trunc(self / other)`, which has
+    /// the same sign as `self`
+    pub fn rem(&self, other: &Self) -> Result<Self> {
+        if other.is_zero() {
+            return Err(NumberError::DivisionByZero);
+        }
+
+        let quotient = match self.div(other)? {
+            Self::Exact(quotient) => Self::Exact(quotient.trunc()),
+            Self::Big(quotient) => Self::from_big(quotient.trunc()),
+            quotient => Self::Approx(quotient.to_f64().trunc()),
+        };
+        self.sub(&other.mul(&quotient)?)
+    }
+
+    pub fn pow(&self, exponent: &Self, precision: Precision) -> Result<Self> {
+        match exponent {
+            Self::Exact(exponent) if exponent.is_integer() => {
+                let exponent = *exponent.numer();
+                match self {
+                    Self::Exact(_) | Self::Big(_) => return self.rational_pow(exponent, precision),
+                    Self::Symbolic(Symbolic { irrational: Irrational::Sqrt(_), .. }) => {
+                        let mut result = Self::integer(1);
+                        let mut base = if exponent < 0 { Self::integer(1).div(self)? } else { self.clone() };
+                        let mut exponent = exponent.unsigned_abs();
+                        while exponent > 0 {
+                            if exponent & 1 == 1 {
+                                result = result.mul(&base)?;
+                            }
+                            exponent >>= 1;
+                            if exponent > 0 {
+                                base = base.mul(&base)?;
+                            }
+                        }
+                        return Ok(result);
+                    },
+                    _ if exponent == 0 => return Ok(Self::integer(1)),
+                    _ if exponent == 1 => return Ok(self.clone()),
+                    _ => {},
+                }
+            },
+            Self::Exact(exponent) if *exponent.denom() == 2 => {
+                if let Self::Exact(base) = self {
+                    if base.is_negative() {
+                        return Err(NumberError::Undefined);
+                    }
+                    return checked_pow(*base, *exponent.numer()).and_then(Self::sqrt_rational);
+                }
+            },
+            _ => {},
+        }
+
+        if self.is_zero() && exponent.is_exact() && exponent.to_f64() < 0.0 {
+            return Err(NumberError::DivisionByZero);
+        }
+
+        let result = self.to_f64().powf(exponent.to_f64());
+        if result.is_nan() && !self.to_f64().is_nan() && !exponent.to_f64().is_nan() {
+            Err(NumberError::Undefined)
+        }
+        else {
+            Ok(Self::Approx(result))
+        }
+    }
+
+    /// A rational raised to an integer power
+    fn rational_pow(&self, exponent: i64, precision: Precision) -> Result<Self> {
+        if let Self::Exact(base) = self {
+            match checked_pow(*base, exponent) {
+                Err(NumberError::Overflow) if precision != Precision::Fixed => {},
+                result => return result.map(Self::Exact),
+            }
+        }
+
+        let max_bits = match precision {
+            Precision::Fixed => return Err(NumberError::Overflow),
+            Precision::Big { max_bits } => max_bits,
+        };
+
+        let base = self.as_big().unwrap();
+        // Each part of the base is at least 2^(bits - 1), so this is a lower
+        // bound on the size of the result, which is found before doing
+        // anything expensive
+        let min_bits = (base.numer().bits() - 1 + base.denom().bits() - 1).saturating_mul(exponent.unsigned_abs());
+        if min_bits > max_bits {
+            return Err(NumberError::TooLarge { max_bits });
+        }
+
+        let result = Pow::pow(&base, exponent.unsigned_abs());
+        Ok(Self::from_big(if exponent < 0 { result.recip() } else { result }))
+    }
+
+    /// `n!`, for a non-negative integer `n`. `step` is called before each
+    /// multiplication, and stops the computation if it fails.
+    pub fn factorial<E>(&self, precision: Precision, step: impl FnMut() -> result::Result<(), E>) -> result::Result<Result<Self>, E> {
+        self.product_of_steps(1, precision, step)
+    }
+
+    /// `n!!`, the product of all integers from `n` down to 1 with the same
+    /// parity as `n`
+    pub fn double_factorial<E>(&self, precision: Precision, step: impl FnMut() -> result::Result<(), E>) -> result::Result<Result<Self>, E> {
+        self.product_of_steps(2, precision, step)
+    }
+
+    /// `n * (n - by) * (n - 2 * by) * ...` down to 1
+    fn product_of_steps<E>(&self, by: i64, precision: Precision, mut step: impl FnMut() -> result::Result<(), E>) -> result::Result<Result<Self>, E> {
+        let n = match self {
+            Self::Exact(n) if n.is_integer() && !n.is_negative() => n.to_integer(),
+            Self::Big(n) if n.is_integer() && !n.is_negative() => return Ok(Err(precision.too_large())),
+            _ => return Ok(Err(NumberError::InvalidFactorial)),
+        };
+
+        let mut result = BigInt::one();
+        let mut factor = n;
+        while factor > 1 {
+            step()?;
+            result *= factor;
+            factor -= by;
+
+            // The result grows with every factor, so it is checked as it
+            // goes rather than at the end, the way `check_size` would
+            let too_large = match precision {
+                Precision::Fixed => result.bits() > 63,
+                // The denominator, 1, takes a bit
+                Precision::Big { max_bits } => result.bits() + 1 > max_bits,
+            };
+            if too_large {
+                return Ok(Err(precision.too_large()));
+            }
+        }
+
+        Ok(Ok(Self::from_big(BigRational::from_integer(result))))
+    }
+
+    /// The gamma function, which extends factorials to all real numbers
+    /// such that `gamma(n) = (n - 1)!`. `step` is called as for `factorial`.
+    pub fn gamma<E>(&self, precision: Precision, step: impl FnMut() -> result::Result<(), E>) -> result::Result<Result<Self>, E> {
+        match self {
+            Self::Exact(n) if n.is_integer() && n.is_positive() => Self::integer(n.to_integer() - 1).factorial(precision, step),
+            _ => Ok(self.gamma_of_real(precision)),
+        }
+    }
+
+    /// `gamma`, for anything but a positive integer that fits in an `i64`
+    fn gamma_of_real(&self, precision: Precision) -> Result<Self> {
+        match self {
+            Self::Exact(n) if n.is_integer() => return Err(NumberError::Undefined),
+            // An integer that needs more than 64 bits has a factorial far
+            // larger than any precision allows
+            Self::Big(n) if n.is_integer() => return Err(if n.is_positive() { precision.too_large() } else { NumberError::Undefined }),
+            _ => {},
+        }
+
+        let x = self.to_f64();
+        let result = gamma(x);
+        if result.is_finite() || x <= 0.0 {
+            return Ok(Self::Approx(result));
+        }
+
+        // Gamma of a large positive number overflows an f64, so its size is
+        // found with Stirling's approximation of ln(gamma(x)) instead
+        match precision {
+            Precision::Big { max_bits } => {
+                let bits = ((x - 0.5) * x.ln() - x + 0.5 * std::f64::consts::TAU.ln()) / std::f64::consts::LN_2;
+                if bits > max_bits as f64 { Err(NumberError::TooLarge { max_bits }) } else { Ok(Self::Approx(f64::INFINITY)) }
+            },
+            Precision::Fixed => Ok(Self::Approx(f64::INFINITY)),
+        }
+    }
+
+    pub fn sqrt(&self) -> Result<Self> {
+        self.pow(&Self::Exact(Rational64::new(1, 2)), Precision::Fixed)
+    }
+
+    /// The square root of a non-negative rational, kept exact by writing it
+    /// as `s * sqrt(t)` where `t` is square-free
+    fn sqrt_rational(n: Rational64) -> Result<Self> {
+        // sqrt(p/q) = sqrt(pq)/q
+        let (p, q) = (*n.numer(), *n.denom());
+        let pq = match p.checked_mul(q) {
+            Some(pq) => pq,
+            None => return Ok(Self::Approx(rational_to_f64(&n).sqrt())),
+        };
+
+        let root = integer_sqrt(pq);
+        if root * root == pq {
+            return Ok(Self::Exact(Rational64::new(root, q)));
+        }
+
+        // Dividing out every factor up to the cube root of what is left leaves
+        // at most two primes, which are a square only if they are equal
+        let mut square_part = 1;
+        let mut square_free = 1;
+        let mut rest = pq;
+        let mut factor: i64 = 2;
+        while factor.checked_mul(factor * factor).is_some_and(|cube| cube <= rest) {
+            while rest % (factor * factor) == 0 {
+                rest /= factor * factor;
+                square_part *= factor;
+            }
+            if rest % factor == 0 {
+                rest /= factor;
+                square_free *= factor;
+            }
+            factor += 1;
+        }
+        let root = integer_sqrt(rest);
+        if root * root == rest {
+            square_part *= root;
+            rest = 1;
+        }
+
+        Ok(Self::symbolic(Rational64::zero(), Rational64::new(square_part, q), Irrational::Sqrt(square_free * rest)))
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(n) => write!(f, "{n}"),
+            Self::Big(n) => write!(f, "{n}"),
+            _ => {
+                let n = self.to_f64();
+                if n.is_infinite() {
+                    write!(f, "{}inf", if n < 0.0 { "-" } else { "" })
+                }
+                else if n != 0.0 && (n.abs() >= 1e16 || n.abs() < 1e-6) {
+                    write!(f, "{n:e}")
+                }
+                else {
+                    write!(f, "{n}")
+                }
+            }
+        }
+    }
+}
+
+pub fn rational_to_f64(n: &Rational64) -> f64 {
+    *n.numer() as f64 / *n.denom() as f64
+}
+
+fn checked<T>(result: Option<T>) -> Result<T> {
+    result.ok_or(NumberError::Overflow)
+}
+
+/// `Ratio::floor` overflows for negative numbers near `i64::MIN`, though the
+/// result always fits
+fn floor(n: &Rational64) -> Rational64 {
+    let (quotient, remainder) = (n.numer() / n.denom(), n.numer() % n.denom());
+    Rational64::from_integer(if remainder < 0 { quotient - 1 } else { quotient })
+}
+
+fn checked_recip(n: Rational64) -> Result<Rational64> {
+    if n.is_zero() {
+        Err(NumberError::DivisionByZero)
+    }
+    else if *n.numer() == i64::MIN {
+        Err(NumberError::Overflow)
+    }
+    else {
+        Ok(n.recip())
+    }
+}
+
+pub fn checked_pow(base: Rational64, exponent: i64) -> Result<Rational64> {
+    let mut base = if exponent < 0 { checked_recip(base)? } else { base };
+    let mut exponent = exponent.unsigned_abs();
+    let mut result = Rational64::one();
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = checked(result.checked_mul(&base))?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = checked(base.checked_mul(&base))?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// The Lanczos approximation of the gamma function
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula, gamma(x) * gamma(1 - x) = pi / sin(pi * x)
+        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+    }
+    else {
+        let x = x - 1.0;
+        let t = x + G + 0.5;
+        let sum = COEFFICIENTS[1..].iter().enumerate()
+            .fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+        (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+    }
+}
+
+fn integer_sqrt(n: i64) -> i64 {
+    let mut root = (n as f64).sqrt() as i64;
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1).checked_mul(root + 1).is_some_and(|square| square <= n) {
+        root += 1;
+    }
+    root
+}
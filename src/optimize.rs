@@ -0,0 +1,164 @@
+use crate::ast::{self, Code, Expression, ExpressionContent, SimpleValue, Value};
+use crate::error::{CompilationError, CompilationErrorKind};
+use crate::number::Number;
+use crate::runtime::{self, Runtime};
+use crate::visit::{self, Fold};
+use crate::Location;
+
+/// Optimizes code with an `Optimizer`
+pub fn optimize(code: Code, runtime: &Runtime) -> Code {
+    Optimizer::new(runtime).fold_code(code)
+}
+
+/// Computes the parts of expressions that don't depend on any variables, so
+/// they are only computed once, and removes operations that do nothing, such
+/// as `x * 1`. Constants are computed with the precision of `runtime`.
+///
+/// Constants that can't be computed, such as `1 / 0`, are left as they are,
+/// so they only fail if they are evaluated. They are also reported as
+/// errors, unless they are in a branch that is removed, as in
+/// `if false then 1 / 0 else 2`.
+pub struct Optimizer<'a> {
+    runtime: &'a Runtime,
+    errors: Vec<CompilationError>,
+}
+
+impl<'a> Optimizer<'a> {
+    pub fn new(runtime: &'a Runtime) -> Self {
+        Self { runtime, errors: Vec::new() }
+    }
+
+    /// The constants that can't be computed in everything folded so far
+    pub fn errors(&self) -> &[CompilationError] {
+        &self.errors
+    }
+
+    /// The simpler form of an expression whose operands have already been
+    /// folded, if it has one
+    fn simplify(&mut self, expr: &Expression) -> Option<Expression> {
+        use ExpressionContent::*;
+
+        let location = expr.location();
+        match expr.content() {
+            Parenthases(inner) => constant(inner).and(Some((**inner).clone())),
+            Interpolated(parts) if parts.iter().all(|part| match part {
+                ast::InterpolatedPart::Hole(expr, _) => constant(expr).is_some(),
+                ast::InterpolatedPart::Text(_) => true,
+            }) => {
+                let result = self.runtime.eval_expr(expr);
+                literal(self.check(result)?, *location)
+            },
+            // Constants such as `pi` and `true`
+            Variable(ident) if self.runtime.is_constant(ident) => literal(self.runtime.resolve_variable(ident, location).ok()?, *location),
+            // Degrees depend on the angle mode when the code is run
+            UnaryExpr(ast::UnaryExpr::Degrees, _) => None,
+            UnaryExpr(op, operand) => {
+                let operand = constant(operand)?;
+                let result = op.eval(&operand, self.runtime, location);
+                literal(self.check(result)?, *location)
+            },
+            BinaryExpr(op, args) => match (constant(&args[0]), constant(&args[1])) {
+                (Some(left), Some(right)) => {
+                    let result = op.eval(&left, &right, self.runtime, location);
+                    literal(self.check(result)?, *location)
+                },
+                _ => identity(op, args).cloned(),
+            },
+            Conditional(branches) => match constant(&branches[0])? {
+                Value::Simple(SimpleValue::Bool(condition)) => {
+                    let (kept, removed) = if condition { (&branches[1], &branches[2]) } else { (&branches[2], &branches[1]) };
+                    self.errors.retain(|error| !contains(removed.location(), error.location()));
+                    Some(kept.clone())
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn check(&mut self, result: runtime::Result<Value>) -> Option<Value> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(CompilationError::new(error.location(), CompilationErrorKind::ConstantError(error.message())));
+                None
+            },
+        }
+    }
+}
+
+impl Fold for Optimizer<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        let expr = visit::fold_expression(self, expr);
+        self.simplify(&expr).unwrap_or(expr)
+    }
+}
+
+/// The value of an expression made only of literals
+fn constant(expr: &Expression) -> Option<Value> {
+    match expr.content() {
+        ExpressionContent::Literal(value) => Some(Value::Simple(value.clone())),
+        ExpressionContent::Str(string) => Some(Value::Str(string.clone())),
+        ExpressionContent::Parenthases(inner) => constant(inner),
+        ExpressionContent::List(elements) => elements.iter().map(constant).collect::<Option<_>>().map(Value::List),
+        _ => None,
+    }
+}
+
+/// An expression for a value, if it can be written as one
+fn literal(value: Value, location: Location) -> Option<Expression> {
+    let content = match value {
+        Value::Simple(value) => ExpressionContent::Literal(value),
+        Value::Str(string) => ExpressionContent::Str(string),
+        Value::List(elements) => ExpressionContent::List(elements.into_iter().map(|element| literal(element, location)).collect::<Option<_>>()?),
+        Value::Function(_) => return None,
+    };
+    Some(Expression::new(content, location))
+}
+
+/// The operand of an operation that leaves it unchanged, e.g. `x` in `x + 0`.
+/// The kept operand can't be something other than a number, as `"a" + 0` is
+/// `"a0"` and `true * 1` is an error.
+fn identity<'e>(op: &ast::BinaryExpr, [left, right]: &'e [Expression; 2]) -> Option<&'e Expression> {
+    use ast::BinaryExpr::*;
+
+    let kept = match op {
+        Multiply if is_integer(right, 1) => left,
+        Multiply if is_integer(left, 1) => right,
+        Add if is_integer(right, 0) => left,
+        Add if is_integer(left, 0) => right,
+        Subtract if is_integer(right, 0) => left,
+        Divide | Power if is_integer(right, 1) => left,
+        _ => return None,
+    };
+    (!is_non_numeric(kept)).then_some(kept)
+}
+
+/// Whether an expression is known to evaluate to something other than a
+/// number or a list of numbers. Variables and calls are assumed to hold
+/// numbers, as they almost always do where these operations are used.
+fn is_non_numeric(expr: &Expression) -> bool {
+    use ast::BinaryExpr::*;
+
+    match expr.content() {
+        ExpressionContent::Literal(value) => !matches!(value, SimpleValue::Number(_)),
+        ExpressionContent::Str(_) | ExpressionContent::Interpolated(_) => true,
+        ExpressionContent::Parenthases(inner) => is_non_numeric(inner),
+        ExpressionContent::UnaryExpr(op, _) => op == &ast::UnaryExpr::Not,
+        // `+` joins strings
+        ExpressionContent::BinaryExpr(Add, args) => args.iter().any(is_non_numeric),
+        ExpressionContent::BinaryExpr(op, _) => !matches!(op, Subtract | Multiply | Divide | Modulo | Remainder | FloorDivide | Power),
+        ExpressionContent::Conditional(branches) => branches[1..].iter().any(is_non_numeric),
+        ExpressionContent::List(elements) => elements.iter().any(is_non_numeric),
+        _ => false,
+    }
+}
+
+/// Whether `inner` is part of `outer`
+fn contains(outer: &Location, inner: &Location) -> bool {
+    outer.begin() <= inner.begin() && inner.end() <= outer.end()
+}
+
+fn is_integer(expr: &Expression, n: i64) -> bool {
+    matches!(constant(expr), Some(Value::Simple(SimpleValue::Number(number))) if number == Number::integer(n))
+}
@@ -0,0 +1,498 @@
+use num_traits::{Signed, Zero};
+
+use crate::ast::{self, BinaryExpr, Expression, ExpressionContent, InterpolatedPart, SimpleValue, UnaryExpr, Value};
+use crate::formatter::{binary_strength, CONDITIONAL, NOT, POSTFIX, PREFIX};
+use crate::number::{Irrational, Number};
+
+/// Binding strength of things that can be the base of a power without
+/// parentheses, such as variables and integers. Fractions and postfix
+/// operators are grouped as bases, so `(1/2)^2` isn't drawn as `1/2²`.
+const ATOM: u8 = POSTFIX + 1;
+
+/// Writes an expression or value as LaTeX, e.g. `\frac{1}{3}` or `x^{2}`
+pub fn latex(expr: &Expression) -> String {
+    Latex.expression(expr)
+}
+
+pub fn value_to_latex(value: &Value) -> String {
+    Latex.value(value)
+}
+
+/// Writes an expression or value as a presentation MathML `<math>` element
+pub fn mathml(expr: &Expression) -> String {
+    MathMl.document(&MathMl.expression(expr))
+}
+
+pub fn value_to_mathml(value: &Value) -> String {
+    MathMl.document(&MathMl.value(value))
+}
+
+/// A mathematical notation. Implementors write the building blocks, such as
+/// fractions and operators, and the provided methods walk expressions and
+/// values, adding parentheses where the structure of the maths needs them.
+///
+/// Every building block must be a single unit of the notation, so that it can
+/// be passed to other building blocks as is.
+pub trait Renderer {
+    /// A number written with decimal digits
+    fn digits(&self, digits: &str) -> String;
+    fn identifier(&self, name: &str) -> String;
+    fn text(&self, text: &str) -> String;
+    /// Parentheses around something
+    fn group(&self, inner: &str) -> String;
+    /// Things written one after the other
+    fn row(&self, parts: &[String]) -> String;
+    fn prefix_operator(&self, op: &UnaryExpr) -> String;
+    fn postfix_operator(&self, op: &UnaryExpr) -> String;
+    fn binary_operator(&self, op: &BinaryExpr) -> String;
+    fn fraction(&self, numerator: &str, denominator: &str) -> String;
+    fn power(&self, base: &str, exponent: &str) -> String;
+    fn sqrt(&self, radicand: &str) -> String;
+    fn floor(&self, inner: &str) -> String;
+    /// A sum or product over `index` from `from` to `to`
+    fn series(&self, is_sum: bool, index: &str, from: &str, to: &str, body: &str) -> String;
+    /// Values, each with the condition for it or `None` for the last one
+    fn cases(&self, cases: &[(String, Option<String>)]) -> String;
+    fn list(&self, elements: &[String]) -> String;
+    fn index(&self, list: &str, index: &str) -> String;
+    fn range(&self, start: &str, end: &str, inclusive: bool, step: Option<&str>) -> String;
+    fn call(&self, f: &str, args: &[String]) -> String;
+
+    fn expression(&self, expr: &Expression) -> String {
+        self.operand(expr, CONDITIONAL)
+    }
+
+    /// Writes an expression that must bind at least as strongly as `min`,
+    /// grouping it if it doesn't
+    fn operand(&self, expr: &Expression, min: u8) -> String {
+        let (text, strength) = self.node(expr);
+        if strength < min {
+            self.group(&text)
+        }
+        else {
+            text
+        }
+    }
+
+    /// Writes an expression along with how strongly it binds
+    fn node(&self, expr: &Expression) -> (String, u8) {
+        use ExpressionContent::*;
+
+        match expr.content() {
+            Parenthases(inner) => self.node(inner),
+            Literal(SimpleValue::Number(n)) => self.number(n),
+            Literal(SimpleValue::Bool(b)) => (self.text(&b.to_string()), ATOM),
+            Str(string) => (self.text(string), ATOM),
+            Interpolated(parts) => {
+                let parts = parts.iter()
+                    .map(|part| match part {
+                        InterpolatedPart::Text(text) => self.text(text),
+                        InterpolatedPart::Hole(expr, _) => self.expression(expr),
+                    })
+                    .collect::<Vec<_>>();
+                (self.row(&parts), ATOM)
+            },
+            Variable(ident) => (self.identifier(ident), ATOM),
+            ExecuteFn((f, args)) => match (f.as_str(), args.as_slice()) {
+                ("sqrt", [radicand]) => (self.sqrt(&self.expression(radicand)), POSTFIX),
+                ("sum" | "product" | "prod", [index, from, to, body]) if matches!(index.content(), Variable(_)) => {
+                    let multiply = binary_strength(&ast::BinaryExpr::Multiply);
+                    let text = self.series(
+                        f == "sum",
+                        &self.expression(index),
+                        &self.expression(from),
+                        &self.expression(to),
+                        &self.operand(body, multiply),
+                    );
+                    (text, multiply)
+                },
+                _ => (self.call(f, &args.iter().map(|arg| self.expression(arg)).collect::<Vec<_>>()), ATOM),
+            },
+            UnaryExpr(op, operand) => match op {
+                ast::UnaryExpr::Negate => (self.row(&[self.prefix_operator(op), self.operand(operand, PREFIX)]), PREFIX),
+                ast::UnaryExpr::Not => {
+                    let operand = self.operand(operand, binary_strength(&ast::BinaryExpr::Equal));
+                    (self.row(&[self.prefix_operator(op), operand]), NOT)
+                },
+                op => (self.row(&[self.operand(operand, ATOM), self.postfix_operator(op)]), POSTFIX),
+            },
+            BinaryExpr(op, args) => match op {
+                ast::BinaryExpr::Divide => (self.fraction(&self.expression(&args[0]), &self.expression(&args[1])), POSTFIX),
+                ast::BinaryExpr::FloorDivide => {
+                    (self.floor(&self.fraction(&self.expression(&args[0]), &self.expression(&args[1]))), ATOM)
+                },
+                ast::BinaryExpr::Power => (self.power(&self.operand(&args[0], ATOM), &self.expression(&args[1])), POSTFIX),
+                op => {
+                    let strength = binary_strength(op);
+                    let (left, right) = if op.is_right_associative() { (strength + 1, strength) } else { (strength, strength + 1) };
+                    (self.row(&[self.operand(&args[0], left), self.binary_operator(op), self.operand(&args[1], right)]), strength)
+                },
+            },
+            Conditional(_) => {
+                // `if a then x else if b then y else z` is drawn as one set of cases
+                let mut cases = Vec::new();
+                let mut expr = expr;
+                while let Conditional(branches) = expr.content() {
+                    let [condition, then, otherwise] = &**branches;
+                    cases.push((self.expression(then), Some(self.expression(condition))));
+                    expr = otherwise;
+                }
+                cases.push((self.expression(expr), None));
+                (self.cases(&cases), CONDITIONAL)
+            },
+            List(elements) => (self.list(&elements.iter().map(|element| self.expression(element)).collect::<Vec<_>>()), ATOM),
+            Index(args) => (self.index(&self.operand(&args[0], ATOM), &self.expression(&args[1])), ATOM),
+            Range { bounds, step, inclusive } => {
+                let step = step.as_ref().map(|step| self.expression(step));
+                (self.range(&self.expression(&bounds[0]), &self.expression(&bounds[1]), *inclusive, step.as_deref()), ATOM)
+            },
+        }
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Simple(SimpleValue::Number(n)) => self.number(n).0,
+            Value::Simple(SimpleValue::Bool(b)) => self.text(&b.to_string()),
+            Value::List(elements) => self.list(&elements.iter().map(|element| self.value(element)).collect::<Vec<_>>()),
+            Value::Function(name) => self.identifier(name),
+            Value::Str(string) => self.text(string),
+        }
+    }
+
+    /// Writes a number along with how strongly it binds. Exact numbers are
+    /// written as fractions and in terms of their irrational part, e.g.
+    /// `\frac{\sqrt{3}}{2}`.
+    fn number(&self, n: &Number) -> (String, u8) {
+        let (negative, numerator, denominator) = match n {
+            Number::Exact(n) => (n.is_negative(), n.numer().unsigned_abs().to_string(), n.denom().to_string()),
+            Number::Big(n) => (n.is_negative(), n.numer().abs().to_string(), n.denom().to_string()),
+            Number::Symbolic(s) => return self.symbolic(s.rational(), s.coefficient(), s.irrational()),
+            Number::Approx(x) if x.is_infinite() => {
+                let infinity = self.identifier("inf");
+                return if x.is_sign_negative() { self.negate(infinity) } else { (infinity, ATOM) };
+            },
+            Number::Approx(x) => {
+                let digits = self.digits(&Number::Approx(x.abs()).to_string());
+                return if x.is_sign_negative() { self.negate(digits) } else { (digits, ATOM) };
+            },
+        };
+
+        let magnitude = if denominator == "1" {
+            (self.digits(&numerator), ATOM)
+        }
+        else {
+            (self.fraction(&self.digits(&numerator), &self.digits(&denominator)), POSTFIX)
+        };
+        if negative { self.negate(magnitude.0) } else { magnitude }
+    }
+
+    /// Writes `rational + coefficient * irrational`
+    fn symbolic(&self, rational: num_rational::Rational64, coefficient: num_rational::Rational64, irrational: Irrational) -> (String, u8) {
+        let irrational = match irrational {
+            Irrational::Pi => self.identifier("pi"),
+            Irrational::E => self.identifier("e"),
+            Irrational::Sqrt(n) => self.sqrt(&self.digits(&n.to_string())),
+        };
+
+        let numerator = match coefficient.numer().unsigned_abs() {
+            1 => irrational,
+            numer => self.row(&[self.digits(&numer.to_string()), irrational]),
+        };
+        let term = match coefficient.denom() {
+            1 => numerator,
+            denom => self.fraction(&numerator, &self.digits(&denom.to_string())),
+        };
+
+        let add = binary_strength(&BinaryExpr::Add);
+        match (rational.is_zero(), coefficient.is_negative()) {
+            (true, false) => (term, POSTFIX),
+            (true, true) => self.negate(term),
+            (false, negative) => {
+                let op = if negative { BinaryExpr::Subtract } else { BinaryExpr::Add };
+                let rational = self.operand_of(self.number(&Number::Exact(rational)), add);
+                (self.row(&[rational, self.binary_operator(&op), term]), add)
+            },
+        }
+    }
+
+    fn negate(&self, magnitude: String) -> (String, u8) {
+        (self.row(&[self.prefix_operator(&UnaryExpr::Negate), magnitude]), PREFIX)
+    }
+
+    /// Groups something already written if it binds less strongly than `min`
+    fn operand_of(&self, (text, strength): (String, u8), min: u8) -> String {
+        if strength < min { self.group(&text) } else { text }
+    }
+
+    /// Wraps a whole rendering up as a document, if the notation needs it
+    fn document(&self, inner: &str) -> String {
+        inner.to_owned()
+    }
+}
+
+/// LaTeX maths, for use inside `$...$`
+pub struct Latex;
+
+impl Latex {
+    fn escape(text: &str) -> String {
+        let mut escaped = String::new();
+        for c in text.chars() {
+            match c {
+                '\\' => escaped.push_str("\\textbackslash{}"),
+                '~' => escaped.push_str("\\textasciitilde{}"),
+                '^' => escaped.push_str("\\textasciicircum{}"),
+                '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                },
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+impl Renderer for Latex {
+    fn digits(&self, digits: &str) -> String {
+        digits.to_owned()
+    }
+
+    fn identifier(&self, name: &str) -> String {
+        match name {
+            "pi" | "π" => "\\pi".to_owned(),
+            "tau" | "τ" => "\\tau".to_owned(),
+            "phi" | "φ" => "\\varphi".to_owned(),
+            "inf" => "\\infty".to_owned(),
+            name if name.chars().count() == 1 => Self::escape(name),
+            name => format!("\\mathrm{{{}}}", Self::escape(name)),
+        }
+    }
+
+    fn text(&self, text: &str) -> String {
+        format!("\\text{{{}}}", Self::escape(text))
+    }
+
+    fn group(&self, inner: &str) -> String {
+        format!("\\left({inner}\\right)")
+    }
+
+    fn row(&self, parts: &[String]) -> String {
+        parts.join(" ")
+    }
+
+    fn prefix_operator(&self, op: &UnaryExpr) -> String {
+        match op {
+            UnaryExpr::Not => "\\lnot".to_owned(),
+            op => op.symbol().to_owned(),
+        }
+    }
+
+    fn postfix_operator(&self, op: &UnaryExpr) -> String {
+        match op {
+            UnaryExpr::Degrees => "^{\\circ}".to_owned(),
+            op => op.symbol().to_owned(),
+        }
+    }
+
+    fn binary_operator(&self, op: &BinaryExpr) -> String {
+        use BinaryExpr::*;
+
+        match op {
+            Multiply => "\\cdot",
+            Modulo => "\\bmod",
+            Remainder => "\\operatorname{rem}",
+            NotEqual => "\\neq",
+            LessOrEqual => "\\leq",
+            GreaterOrEqual => "\\geq",
+            And => "\\land",
+            Or => "\\lor",
+            Equal => "=",
+            op => op.symbol(),
+        }.to_owned()
+    }
+
+    fn fraction(&self, numerator: &str, denominator: &str) -> String {
+        format!("\\frac{{{numerator}}}{{{denominator}}}")
+    }
+
+    fn power(&self, base: &str, exponent: &str) -> String {
+        format!("{base}^{{{exponent}}}")
+    }
+
+    fn sqrt(&self, radicand: &str) -> String {
+        format!("\\sqrt{{{radicand}}}")
+    }
+
+    fn floor(&self, inner: &str) -> String {
+        format!("\\left\\lfloor {inner} \\right\\rfloor")
+    }
+
+    fn series(&self, is_sum: bool, index: &str, from: &str, to: &str, body: &str) -> String {
+        format!("{}_{{{index} = {from}}}^{{{to}}} {body}", if is_sum { "\\sum" } else { "\\prod" })
+    }
+
+    fn cases(&self, cases: &[(String, Option<String>)]) -> String {
+        let cases = cases.iter()
+            .map(|(value, condition)| match condition {
+                Some(condition) => format!("{value} & \\text{{if }} {condition}"),
+                None => format!("{value} & \\text{{otherwise}}"),
+            })
+            .collect::<Vec<_>>();
+        format!("\\begin{{cases}} {} \\end{{cases}}", cases.join(" \\\\ "))
+    }
+
+    fn list(&self, elements: &[String]) -> String {
+        format!("\\left[{}\\right]", elements.join(", "))
+    }
+
+    fn index(&self, list: &str, index: &str) -> String {
+        // The braces stop `x[0][1]` from being a double subscript
+        format!("{{{list}}}_{{{index}}}")
+    }
+
+    fn range(&self, start: &str, end: &str, inclusive: bool, step: Option<&str>) -> String {
+        let mut text = format!("\\left[{start}, {end}\\right{}", if inclusive { "]" } else { ")" });
+        if let Some(step) = step {
+            text.push_str(&format!(" \\text{{ step }} {step}"));
+        }
+        text
+    }
+
+    fn call(&self, f: &str, args: &[String]) -> String {
+        let name = match f {
+            "sin" | "cos" | "tan" => format!("\\{f}"),
+            "asin" | "acos" | "atan" => format!("\\arc{}", &f[1..]),
+            "gamma" => "\\Gamma".to_owned(),
+            f => self.identifier(f),
+        };
+        format!("{name}{}", self.group(&args.join(", ")))
+    }
+}
+
+/// Presentation MathML
+pub struct MathMl;
+
+impl MathMl {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    fn operator(symbol: &str) -> String {
+        format!("<mo>{}</mo>", Self::escape(symbol))
+    }
+}
+
+impl Renderer for MathMl {
+    fn digits(&self, digits: &str) -> String {
+        format!("<mn>{digits}</mn>")
+    }
+
+    fn identifier(&self, name: &str) -> String {
+        let name = match name {
+            "pi" => "π",
+            "tau" => "τ",
+            "phi" => "φ",
+            "inf" => "∞",
+            name => name,
+        };
+        format!("<mi>{}</mi>", Self::escape(name))
+    }
+
+    fn text(&self, text: &str) -> String {
+        format!("<mtext>{}</mtext>", Self::escape(text))
+    }
+
+    fn group(&self, inner: &str) -> String {
+        format!("<mrow><mo>(</mo>{inner}<mo>)</mo></mrow>")
+    }
+
+    fn row(&self, parts: &[String]) -> String {
+        format!("<mrow>{}</mrow>", parts.concat())
+    }
+
+    fn prefix_operator(&self, op: &UnaryExpr) -> String {
+        match op {
+            UnaryExpr::Not => Self::operator("¬"),
+            op => Self::operator(op.symbol()),
+        }
+    }
+
+    fn postfix_operator(&self, op: &UnaryExpr) -> String {
+        Self::operator(op.symbol())
+    }
+
+    fn binary_operator(&self, op: &BinaryExpr) -> String {
+        use BinaryExpr::*;
+
+        Self::operator(match op {
+            Multiply => "·",
+            Modulo => "mod",
+            Remainder => "rem",
+            NotEqual => "≠",
+            LessOrEqual => "≤",
+            GreaterOrEqual => "≥",
+            And => "∧",
+            Or => "∨",
+            Equal => "=",
+            op => op.symbol(),
+        })
+    }
+
+    fn fraction(&self, numerator: &str, denominator: &str) -> String {
+        format!("<mfrac>{numerator}{denominator}</mfrac>")
+    }
+
+    fn power(&self, base: &str, exponent: &str) -> String {
+        format!("<msup>{base}{exponent}</msup>")
+    }
+
+    fn sqrt(&self, radicand: &str) -> String {
+        format!("<msqrt>{radicand}</msqrt>")
+    }
+
+    fn floor(&self, inner: &str) -> String {
+        format!("<mrow><mo>⌊</mo>{inner}<mo>⌋</mo></mrow>")
+    }
+
+    fn series(&self, is_sum: bool, index: &str, from: &str, to: &str, body: &str) -> String {
+        format!(
+            "<mrow><munderover><mo>{}</mo><mrow>{index}<mo>=</mo>{from}</mrow>{to}</munderover>{body}</mrow>",
+            if is_sum { "∑" } else { "∏" },
+        )
+    }
+
+    fn cases(&self, cases: &[(String, Option<String>)]) -> String {
+        let rows = cases.iter()
+            .map(|(value, condition)| match condition {
+                Some(condition) => format!("<mtr><mtd>{value}</mtd><mtd><mtext>if </mtext>{condition}</mtd></mtr>"),
+                None => format!("<mtr><mtd>{value}</mtd><mtd><mtext>otherwise</mtext></mtd></mtr>"),
+            })
+            .collect::<String>();
+        format!("<mrow><mo>{{</mo><mtable>{rows}</mtable></mrow>")
+    }
+
+    fn list(&self, elements: &[String]) -> String {
+        format!("<mrow><mo>[</mo>{}<mo>]</mo></mrow>", elements.join("<mo>,</mo>"))
+    }
+
+    fn index(&self, list: &str, index: &str) -> String {
+        format!("<msub>{list}{index}</msub>")
+    }
+
+    fn range(&self, start: &str, end: &str, inclusive: bool, step: Option<&str>) -> String {
+        let step = step.map(|step| format!("<mtext> step </mtext>{step}")).unwrap_or_default();
+        format!("<mrow><mo>[</mo>{start}<mo>,</mo>{end}<mo>{}</mo>{step}</mrow>", if inclusive { "]" } else { ")" })
+    }
+
+    fn call(&self, f: &str, args: &[String]) -> String {
+        let name = match f {
+            "gamma" => self.identifier("Γ"),
+            f => self.identifier(f),
+        };
+        format!("<mrow>{name}{}</mrow>", self.group(&args.join("<mo>,</mo>")))
+    }
+
+    fn document(&self, inner: &str) -> String {
+        format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{inner}</math>")
+    }
+}
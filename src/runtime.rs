@@ -1,35 +1,292 @@
-use crate::{ast::{Args, Expression, ExpressionContent, Value}, LocatableContent, Location};
-use std::{collections::HashMap, rc::Rc, result};
+use crate::{ast::{self, Args, CodeObject, CodeObjectContent, Expression, ExpressionContent, SimpleValue, Value}, number::{self, Number, Precision}, LocatableContent, Location};
+use num_rational::Rational64;
+use std::{cmp, cell::{Cell, RefCell}, collections::HashMap, rc::Rc, result, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Instant};
 use native_functions::*;
 use runtime_errors::*;
+use user_functions::UserFunction;
 use crate::tokenize::Ident;
 
 pub mod native_functions;
 pub mod runtime_errors;
+pub mod user_functions;
 
 pub type Result<T> = result::Result<T, Box<dyn RuntimeError>>;
 
+/// Deeper recursion is almost always a mistake. This alone doesn't keep the
+/// stack from overflowing, as each call can nest many expressions, which
+/// `DEFAULT_MAX_DEPTH` limits.
+const DEFAULT_MAX_CALL_DEPTH: usize = 200;
+
+/// Counts the expressions in the bodies of called functions too, except the
+/// brackets and conditionals around a tail call, which take no stack. In a
+/// debug build each level takes up to about 6 KiB, so this fits in the main
+/// thread's 8 MiB stack, but not in the 2 MiB of a spawned thread.
+const DEFAULT_MAX_DEPTH: usize = 800;
+
+/// The most elements a range can have, so that a range like `1..10^18`
+/// fails quickly rather than using all memory
+const MAX_RANGE_LEN: usize = 1 << 20;
+
 
 
 
 
 pub trait Function {
-  fn run(&self, runtime: &Runtime, args: &Args) -> Option<Result<Value>>;
+  fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>>;
+
+  /// Runs the function before its arguments are evaluated, for functions
+  /// that decide how to evaluate them. Returns `None` to have the arguments
+  /// evaluated and passed to `run` instead.
+  fn run_unevaluated(&self, _runtime: &Runtime, _args: &[Expression], _location: &Location) -> Option<Result<Value>> {
+    None
+  }
+
+  /// Allows tail calls to user-defined functions to be evaluated without
+  /// growing the stack
+  fn as_user_function(&self) -> Option<&UserFunction> {
+    None
+  }
+}
+
+/// A set of variables that are only visible while something is evaluated
+struct Frame {
+  variables: HashMap<Ident, Value>,
+  /// Whether the variables of the enclosing frame are still visible, which
+  /// is not the case for the arguments of a calling function
+  inherits: bool,
+}
+
+/// The unit that trigonometric functions take and return angles in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AngleMode {
+  Radians,
+  Degrees,
+  Gradians,
+}
+
+impl AngleMode {
+  /// The size of a full turn in this unit, divided by 2π
+  fn scale(self) -> Option<Number> {
+    match self {
+      AngleMode::Radians => None,
+      AngleMode::Degrees => Some(Number::integer(180)),
+      AngleMode::Gradians => Some(Number::integer(200)),
+    }
+  }
+
+  pub fn angle_to_radians(self, angle: &Number) -> number::Result<Number> {
+    match self.scale() {
+      Some(scale) => angle.mul(&Number::pi())?.div(&scale),
+      None => Ok(angle.clone()),
+    }
+  }
+
+  pub fn radians_to_angle(self, radians: &Number) -> number::Result<Number> {
+    match self.scale() {
+      Some(scale) => radians.mul(&scale)?.div(&Number::pi()),
+      None => Ok(radians.clone()),
+    }
+  }
+}
+
+/// Cancels evaluation in a `Runtime`, possibly from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancellationHandle(Arc<AtomicBool>);
+
+impl CancellationHandle {
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+
+  /// Allows evaluation to continue after it has been cancelled
+  pub fn reset(&self) {
+    self.0.store(false, Ordering::Relaxed);
+  }
 }
 
 pub struct Runtime {
-  functions: HashMap<String, Rc<dyn Function>>
+  functions: HashMap<String, Rc<dyn Function>>,
+  /// Named values that cannot be reassigned
+  constants: HashMap<Ident, Value>,
+  variables: HashMap<Ident, Value>,
+  /// The arguments of each user-defined function currently being called,
+  /// and any scopes nested inside them
+  frames: RefCell<Vec<Frame>>,
+  /// The number of function calls currently being evaluated, not counting
+  /// tail calls
+  call_depth: Cell<usize>,
+  max_call_depth: usize,
+  /// The number of expressions currently being evaluated inside each other,
+  /// counted across function calls
+  depth: Cell<usize>,
+  max_depth: usize,
+  /// The number of evaluation steps that may still be taken, if limited
+  fuel: Cell<Option<u64>>,
+  deadline: Option<Instant>,
+  cancellation: CancellationHandle,
+  angle_mode: AngleMode,
+  precision: Precision,
 }
 
 impl Runtime {
   pub fn new() -> Self {
+    let pi = Value::Simple(SimpleValue::Number(Number::pi()));
+    let tau = Value::Simple(SimpleValue::Number(Number::pi().mul(&Number::integer(2)).unwrap()));
+    let phi = Value::Simple(SimpleValue::Number(Number::phi()));
+
     Self {
       functions: HashMap::from([
         (String::from("print"), Rc::new(Print) as Rc<dyn Function>),
-      ])
+        (String::from("sin"), Rc::new(Sin) as Rc<dyn Function>),
+        (String::from("cos"), Rc::new(Cos) as Rc<dyn Function>),
+        (String::from("tan"), Rc::new(Tan) as Rc<dyn Function>),
+        (String::from("asin"), Rc::new(Asin) as Rc<dyn Function>),
+        (String::from("acos"), Rc::new(Acos) as Rc<dyn Function>),
+        (String::from("atan"), Rc::new(Atan) as Rc<dyn Function>),
+        (String::from("deg"), Rc::new(Deg) as Rc<dyn Function>),
+        (String::from("rad"), Rc::new(Rad) as Rc<dyn Function>),
+        (String::from("sqrt"), Rc::new(Sqrt) as Rc<dyn Function>),
+        (String::from("gamma"), Rc::new(Gamma) as Rc<dyn Function>),
+        (String::from("len"), Rc::new(Len) as Rc<dyn Function>),
+        (String::from("sum"), Rc::new(Sum) as Rc<dyn Function>),
+        (String::from("product"), Rc::new(Product) as Rc<dyn Function>),
+        (String::from("prod"), Rc::new(Product) as Rc<dyn Function>),
+        (String::from("seq"), Rc::new(Seq) as Rc<dyn Function>),
+        (String::from("str"), Rc::new(Str) as Rc<dyn Function>),
+        (String::from("format"), Rc::new(Format) as Rc<dyn Function>),
+      ]),
+      constants: HashMap::from([
+        (String::from("pi"), pi.clone()),
+        (String::from("π"), pi),
+        (String::from("e"), Value::Simple(SimpleValue::Number(Number::e()))),
+        (String::from("tau"), tau.clone()),
+        (String::from("τ"), tau),
+        (String::from("phi"), phi.clone()),
+        (String::from("φ"), phi),
+        (String::from("true"), Value::bool(true)),
+        (String::from("false"), Value::bool(false)),
+        (String::from("inf"), Value::Simple(SimpleValue::Number(Number::Approx(f64::INFINITY)))),
+      ]),
+      variables: HashMap::new(),
+      frames: RefCell::new(Vec::new()),
+      call_depth: Cell::new(0),
+      max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+      depth: Cell::new(0),
+      max_depth: DEFAULT_MAX_DEPTH,
+      fuel: Cell::new(None),
+      deadline: None,
+      cancellation: CancellationHandle::default(),
+      angle_mode: AngleMode::Radians,
+      precision: Precision::Fixed,
     }
   }
 
+  pub fn angle_mode(&self) -> AngleMode {
+    self.angle_mode
+  }
+
+  pub fn set_angle_mode(&mut self, angle_mode: AngleMode) {
+    self.angle_mode = angle_mode;
+  }
+
+  pub fn precision(&self) -> Precision {
+    self.precision
+  }
+
+  /// Sets how large exact rationals can become. With `Precision::Big`,
+  /// results that need more bits than allowed fail with a `TooLarge` error
+  /// instead of taking ever more memory.
+  pub fn set_precision(&mut self, precision: Precision) {
+    self.precision = precision;
+  }
+
+  pub fn max_call_depth(&self) -> usize {
+    self.max_call_depth
+  }
+
+  /// Sets how deeply function calls can be nested before a
+  /// `RecursionLimitError` is raised. Tail calls are not counted.
+  pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+    self.max_call_depth = max_call_depth;
+  }
+
+  pub fn max_depth(&self) -> usize {
+    self.max_depth
+  }
+
+  /// Sets how deeply expressions can be nested while being evaluated before
+  /// a `RecursionLimitError` is raised. Expressions in the bodies of called
+  /// functions are nested inside the call, so this limits recursion as well
+  /// as the shape of the code.
+  pub fn set_max_depth(&mut self, max_depth: usize) {
+    self.max_depth = max_depth;
+  }
+
+  pub fn fuel(&self) -> Option<u64> {
+    self.fuel.get()
+  }
+
+  /// Limits the number of evaluation steps that can be taken before an
+  /// `OutOfFuelError` is raised. Every expression evaluated, and every
+  /// iteration of a loop inside a native function, takes one step. The
+  /// budget is shared by everything run until it is set again.
+  pub fn set_fuel(&mut self, fuel: Option<u64>) {
+    self.fuel.set(fuel);
+  }
+
+  pub fn deadline(&self) -> Option<Instant> {
+    self.deadline
+  }
+
+  /// Sets a time after which evaluation fails with a `DeadlineExceededError`
+  pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+    self.deadline = deadline;
+  }
+
+  /// A handle which can be used to stop evaluation from another thread,
+  /// after which it fails with a `CancelledError`
+  pub fn cancellation_handle(&self) -> CancellationHandle {
+    self.cancellation.clone()
+  }
+
+  /// Takes one step of evaluation, failing if the runtime has run out of
+  /// fuel or time, or if evaluation has been cancelled
+  pub fn step(&self, location: &Location) -> Result<()> {
+    if let Some(fuel) = self.fuel.get() {
+      if fuel == 0 {
+        return Err(Box::new(OutOfFuelError::new(*location)));
+      }
+      self.fuel.set(Some(fuel - 1));
+    }
+
+    if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+      Err(Box::new(DeadlineExceededError::new(*location)))
+    }
+    else if self.cancellation.is_cancelled() {
+      Err(Box::new(CancelledError::new(*location)))
+    }
+    else {
+      Ok(())
+    }
+  }
+
+  /// Runs `f` as a function call, failing if that would nest calls more
+  /// deeply than allowed
+  pub fn enter_call<T>(&self, location: &Location, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    if self.call_depth.get() >= self.max_call_depth {
+      return Err(Box::new(RecursionLimitError::new(self.max_call_depth, *location)));
+    }
+
+    self.call_depth.set(self.call_depth.get() + 1);
+    let result = f();
+    self.call_depth.set(self.call_depth.get() - 1);
+    result
+  }
+
   pub fn resolve_function(&self, ident: &Ident, location: &Location) -> Result<Rc<dyn Function>> {
     match self.functions.get(ident) {
       Some(f) => Ok(Rc::clone(f)),
@@ -37,23 +294,236 @@ impl Runtime {
     }
   }
 
+  pub fn resolve_variable(&self, ident: &Ident, location: &Location) -> Result<Value> {
+    for frame in self.frames.borrow().iter().rev() {
+      if let Some(value) = frame.variables.get(ident) {
+        return Ok(value.clone());
+      }
+      if !frame.inherits {
+        break;
+      }
+    }
+
+    match self.variables.get(ident).or_else(|| self.constants.get(ident)) {
+      Some(value) => Ok(value.clone()),
+      // A function's name can be used as a value to pass it to another
+      None if self.functions.contains_key(ident) => Ok(Value::Function(ident.clone())),
+      None => Err(Box::new(ResolutionError::new(LocatableContent::new(ident.clone(), *location))))
+    }
+  }
+
+  /// Runs `f` with `frame` as the innermost set of variables, which hides
+  /// the variables of any function that is currently being called
+  pub fn with_frame<T>(&self, frame: HashMap<Ident, Value>, f: impl FnOnce() -> T) -> T {
+    self.frames.borrow_mut().push(Frame { variables: frame, inherits: false });
+    let result = f();
+    self.frames.borrow_mut().pop();
+    result
+  }
+
+  /// Runs `f` with `ident` bound to `value`, on top of whatever variables
+  /// are already visible
+  pub fn with_scope<T>(&self, ident: &Ident, value: Value, location: &Location, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    if self.is_constant(ident) {
+      return Err(Box::new(ConstantAssignmentError::new(LocatableContent::new(ident.clone(), *location))));
+    }
+
+    let variables = HashMap::from([(ident.clone(), value)]);
+    self.frames.borrow_mut().push(Frame { variables, inherits: true });
+    let result = f();
+    self.frames.borrow_mut().pop();
+    result
+  }
+
+  pub fn is_constant(&self, ident: &Ident) -> bool {
+    self.constants.contains_key(ident)
+  }
+
+  pub fn assign(&mut self, ident: &Ident, value: Value, location: &Location) -> Result<()> {
+    if self.is_constant(ident) {
+      Err(Box::new(ConstantAssignmentError::new(LocatableContent::new(ident.clone(), *location))))
+    }
+    else {
+      self.variables.insert(ident.clone(), value);
+      Ok(())
+    }
+  }
+
+  /// Executes a single statement, returning its value if it has one
+  pub fn exec(&mut self, object: &CodeObject) -> Result<Option<Value>> {
+    use CodeObjectContent::*;
+
+    match object.content() {
+      ExecuteFn((f, raw_args)) => self.call(f, raw_args, object.location()),
+      Expression(expr) => self.eval_expr(expr).map(Some),
+      Assign(ident, expr) => {
+        let value = self.eval_expr(expr)?;
+        self.assign(ident, value, object.location())?;
+        Ok(None)
+      },
+      DefineFn(ident, params, clauses) => {
+        if let Some(param) = params.iter().find(|param| self.is_constant(param)) {
+          return Err(Box::new(ConstantAssignmentError::new(LocatableContent::new(param.clone(), *object.location()))));
+        }
+
+        let function = UserFunction::new(ident.clone(), params.clone(), clauses.clone());
+        self.functions.insert(ident.clone(), Rc::new(function));
+        Ok(None)
+      }
+    }
+  }
+
+  fn call(&self, f: &Ident, raw_args: &[Expression], location: &Location) -> Result<Option<Value>> {
+    let function = match self.resolve_function(f, location) {
+      Ok(function) => function,
+      Err(error) => match (self.resolve_variable(f, location), raw_args) {
+        // A variable holding a function can be called like one
+        (Ok(Value::Function(name)), _) => self.resolve_function(&name, location)?,
+        // `a(b)` is a multiplication when `a` is a variable rather than a function
+        (Ok(value), [arg]) => return ast::BinaryExpr::Multiply.eval(&value, &self.eval_expr(arg)?, self, location).map(Some),
+        _ => return Err(error),
+      },
+    };
+
+    if let Some(result) = function.run_unevaluated(self, raw_args, location) {
+      return result.map(Some);
+    }
+
+    function
+      .run(self, &raw_args.iter()
+        .map(|arg| self.eval_expr(arg))
+        .collect::<Result<Args>>()?, location)
+      .transpose()
+  }
+
+  /// Calls a function value with already evaluated arguments
+  pub fn call_value(&self, function: &Value, args: &Args, location: &Location) -> Result<Value> {
+    let name = match function {
+      Value::Function(name) => name,
+      _ => return Err(Box::new(TypeError::new("function", function.type_name(), *location))),
+    };
+
+    self.resolve_function(name, location)?
+      .run(self, args, location)
+      .unwrap_or_else(|| Err(Box::new(NonReturingFunctionError::new(*location))))
+  }
+
+  /// The numbers from `start` to `end`, `step` apart
+  fn eval_range(&self, bounds: &[Expression; 2], step: Option<&Expression>, inclusive: bool, location: &Location) -> Result<Value> {
+    let [start_expr, end_expr] = bounds;
+    let start = self.eval_expr(start_expr)?.as_number(start_expr.location())?;
+    let end = self.eval_expr(end_expr)?.as_number(end_expr.location())?;
+    for (bound, expr) in [(&start, start_expr), (&end, end_expr)] {
+      if !bound.to_f64().is_finite() {
+        return Err(Box::new(InfiniteRangeError::new(*expr.location())));
+      }
+    }
+    let step = match step {
+      Some(step) => Value::Simple(SimpleValue::Number(self.eval_expr(step)?.as_number(step.location())?)),
+      None => Value::number(Rational64::from_integer(1)),
+    };
+
+    // Counting down if the step is negative
+    let direction = match step.as_number(location)?.compare(&Number::integer(0)) {
+      Some(cmp::Ordering::Equal) | None => return Err(Box::new(ZeroStepError::new(*location))),
+      Some(ordering) => ordering.reverse(),
+    };
+
+    let len = (end.to_f64() - start.to_f64()) / step.as_number(location)?.to_f64();
+    if len > MAX_RANGE_LEN as f64 {
+      return Err(Box::new(RangeTooLongError::new(MAX_RANGE_LEN, *location)));
+    }
+
+    let mut value = Value::Simple(SimpleValue::Number(start));
+    let mut values = Vec::new();
+    loop {
+      match value.as_number(location)?.compare(&end) {
+        Some(ordering) if ordering == direction || inclusive && ordering == cmp::Ordering::Equal => {},
+        _ => break,
+      }
+
+      // The estimate of the length above can be off by one
+      if values.len() == MAX_RANGE_LEN {
+        return Err(Box::new(RangeTooLongError::new(MAX_RANGE_LEN, *location)));
+      }
+
+      self.step(location)?;
+      let next = ast::BinaryExpr::Add.eval(&value, &step, self, location)?;
+      values.push(value);
+      value = next;
+    }
+
+    Ok(Value::List(values))
+  }
+
   pub fn eval_expr(&self, expr: &Expression) -> Result<Value> {
+    self.step(expr.location())?;
+
+    if self.depth.get() >= self.max_depth {
+      return Err(Box::new(RecursionLimitError::expressions(self.max_depth, *expr.location())));
+    }
+
+    self.depth.set(self.depth.get() + 1);
+    let result = self.eval_content(expr);
+    self.depth.set(self.depth.get() - 1);
+    result
+  }
+
+  fn eval_content(&self, expr: &Expression) -> Result<Value> {
     use ExpressionContent::*;
 
     match expr.content() {
       ExecuteFn((f, raw_args)) 
-        => Ok(self
-            .resolve_function(f, expr.location())?
-            .run(self, &raw_args.iter()
-              .map(|arg| self.eval_expr(arg))
-              .collect::<Result<Args>>()?)
-            .unwrap_or_else(|| Err(Box::new(NonReturingFunctionError::new(*expr.location()))))?),
+        => self.call(f, raw_args, expr.location())?
+            .ok_or_else(|| Box::new(NonReturingFunctionError::new(*expr.location())) as Box<dyn RuntimeError>),
       Parenthases(content) => self.eval_expr(content),
-      Literal(content) => Ok(Value::Simple(*content)),
-      BinaryExpr(expr, args) => {
+      Literal(content) => Ok(Value::Simple(content.clone())),
+      Str(string) => Ok(Value::Str(string.clone())),
+      Interpolated(parts) => {
+        let mut string = String::new();
+        for part in parts {
+          match part {
+            ast::InterpolatedPart::Text(text) => string.push_str(text),
+            ast::InterpolatedPart::Hole(expr, spec) => string.push_str(&spec.unwrap_or_default().apply(&self.eval_expr(expr)?)),
+          }
+        }
+        Ok(Value::Str(string))
+      },
+      Variable(ident) => self.resolve_variable(ident, expr.location()),
+      UnaryExpr(op, operand) => op.eval(&self.eval_expr(operand)?, self, expr.location()),
+      // The right side of a logical operator is only evaluated if needed
+      BinaryExpr(op @ (ast::BinaryExpr::And | ast::BinaryExpr::Or), args) => {
+        let left = self.eval_expr(&args[0])?.as_bool(args[0].location())?;
+        if left == (op == &ast::BinaryExpr::Or) {
+          Ok(Value::bool(left))
+        }
+        else {
+          Ok(Value::bool(self.eval_expr(&args[1])?.as_bool(args[1].location())?))
+        }
+      },
+      BinaryExpr(op, args) => {
         let args = args.iter().map(|arg| self.eval_expr(arg)).collect::<Result<Args>>()?;
-        expr.eval(&args[0], &args[1])
+        op.eval(&args[0], &args[1], self, expr.location())
+      },
+      List(elements) => elements.iter().map(|element| self.eval_expr(element)).collect::<Result<_>>().map(Value::List),
+      Range { bounds, step, inclusive } => self.eval_range(bounds, step.as_deref(), *inclusive, expr.location()),
+      Index(args) => self.eval_expr(&args[0])?.index(&self.eval_expr(&args[1])?, args[1].location()),
+      // Only the chosen branch is evaluated
+      Conditional(branches) => {
+        let [condition, then, otherwise] = &**branches;
+        if self.eval_expr(condition)?.as_bool(condition.location())? {
+          self.eval_expr(then)
+        }
+        else {
+          self.eval_expr(otherwise)
+        }
       }
     }
   }
-}
\ No newline at end of file
+}
+
+impl Default for Runtime {
+  fn default() -> Self {
+    Self::new()
+  }
+}
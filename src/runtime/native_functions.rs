@@ -1,11 +1,391 @@
-use super::{Function, Runtime, Result};
-use crate::ast::{Args, Value};
+use std::ops::RangeInclusive;
+
+use num_rational::Rational64;
+use num_traits::{CheckedMul, Zero};
+
+use super::{AngleMode, Function, Runtime, Result};
+use super::runtime_errors::{ArgumentCountError, ArithmeticError, FormatTemplateError, TypeError};
+use crate::ast::{Args, BinaryExpr, Expression, ExpressionContent, SimpleValue, Value};
+use crate::format_spec::FormatSpec;
+use crate::number::{self, Irrational, Number, NumberError};
+use crate::{LocatableContent, Location};
+
+fn check_arg_count(args: &Args, expected: usize, location: &Location) -> Result<()> {
+    if args.len() == expected {
+        Ok(())
+    }
+    else {
+        Err(Box::new(ArgumentCountError::new(expected, args.len(), *location)))
+    }
+}
+
+fn number_arg(args: &Args, index: usize, location: &Location) -> Result<Number> {
+    args[index].as_number(location)
+}
+
+fn number_result(result: std::result::Result<Number, NumberError>, runtime: &Runtime, location: &Location) -> Result<Value> {
+    result
+        .and_then(|n| n.check_size(runtime.precision()))
+        .map(|n| Value::Simple(SimpleValue::Number(n)))
+        .map_err(|e| Box::new(ArithmeticError::new(LocatableContent::new(e, *location))) as Box<_>)
+}
+
+/// If `angle` is a multiple of pi/12, returns that multiple reduced to `0..24`
+fn pi_twelfths(angle: &Number) -> Option<i64> {
+    let twelfths = angle.as_pi_multiple()?.checked_mul(&Rational64::from_integer(12))?;
+    twelfths.is_integer().then(|| twelfths.to_integer().rem_euclid(24))
+}
+
+/// The exact value of `sin(k * pi / 12)`, for the `k` where it has one
+fn exact_sin(k: i64) -> Option<Number> {
+    let (k, sign) = if k >= 12 { (k - 12, -1) } else { (k, 1) };
+    let half_sqrt = |n| Number::symbolic(Rational64::zero(), Rational64::new(sign, 2), Irrational::Sqrt(n));
+
+    match k {
+        0 => Some(Number::integer(0)),
+        2 | 10 => Some(Number::Exact(Rational64::new(sign, 2))),
+        3 | 9 => Some(half_sqrt(2)),
+        4 | 8 => Some(half_sqrt(3)),
+        6 => Some(Number::integer(sign)),
+        _ => None,
+    }
+}
+
+/// The exact value of `tan(k * pi / 12)`, for the `k` where it has one
+fn exact_tan(k: i64) -> Option<Number> {
+    let cos = exact_sin((k + 6) % 24)?;
+    if cos.is_zero() {
+        None
+    }
+    else {
+        exact_sin(k)?.div(&cos).ok()
+    }
+}
+
+/// The angle `k * pi / 12` with `k` in `range` for which `f(k)` is exactly
+/// `value`, if there is one
+fn exact_inverse(value: &Number, range: RangeInclusive<i64>, f: fn(i64) -> Option<Number>) -> Option<Number> {
+    range
+        .into_iter()
+        .find(|k| f(k.rem_euclid(24)).as_ref() == Some(value))
+        .map(|k| Number::symbolic(Rational64::zero(), Rational64::new(k, 12), Irrational::Pi))
+}
+
+fn sin(angle: &Number) -> Number {
+    pi_twelfths(angle).and_then(exact_sin)
+        .unwrap_or_else(|| Number::Approx(angle.to_f64().sin()))
+}
+
+fn cos(angle: &Number) -> Number {
+    pi_twelfths(angle).and_then(|k| exact_sin((k + 6) % 24))
+        .unwrap_or_else(|| Number::Approx(angle.to_f64().cos()))
+}
+
+fn tan(angle: &Number) -> number::Result<Number> {
+    match pi_twelfths(angle) {
+        Some(k) if exact_sin((k + 6) % 24) == Some(Number::integer(0)) => Err(NumberError::Undefined),
+        Some(k) => Ok(exact_tan(k).unwrap_or_else(|| Number::Approx(angle.to_f64().tan()))),
+        None => Ok(Number::Approx(angle.to_f64().tan())),
+    }
+}
+
+/// Runs a function of a single number
+fn unary_function(args: &Args, runtime: &Runtime, location: &Location, f: impl FnOnce(Number) -> number::Result<Number>) -> Option<Result<Value>> {
+    Some(check_arg_count(args, 1, location)
+        .and_then(|_| number_arg(args, 0, location))
+        .and_then(|n| number_result(f(n), runtime, location)))
+}
 
 pub struct Print;
 
 impl Function for Print {
-    fn run(&self, _: &Runtime, args: &Args) -> Option<Result<Value>> {
-        println!("{:?}", args[0]);
+    fn run(&self, _: &Runtime, args: &Args, _: &Location) -> Option<Result<Value>> {
+        println!("{}", args.iter().map(Value::to_string).collect::<String>());
         None
     }
-}
\ No newline at end of file
+}
+
+pub struct Sin;
+
+impl Function for Sin {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |angle| runtime.angle_mode().angle_to_radians(&angle).map(|angle| sin(&angle)))
+    }
+}
+
+pub struct Cos;
+
+impl Function for Cos {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |angle| runtime.angle_mode().angle_to_radians(&angle).map(|angle| cos(&angle)))
+    }
+}
+
+pub struct Tan;
+
+impl Function for Tan {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |angle| tan(&runtime.angle_mode().angle_to_radians(&angle)?))
+    }
+}
+
+pub struct Asin;
+
+impl Function for Asin {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |n| {
+            if n.to_f64().abs() > 1.0 {
+                return Err(NumberError::Undefined);
+            }
+            let radians = exact_inverse(&n, -6..=6, exact_sin)
+                .unwrap_or_else(|| Number::Approx(n.to_f64().asin()));
+            runtime.angle_mode().radians_to_angle(&radians)
+        })
+    }
+}
+
+pub struct Acos;
+
+impl Function for Acos {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |n| {
+            if n.to_f64().abs() > 1.0 {
+                return Err(NumberError::Undefined);
+            }
+            let radians = exact_inverse(&n, 0..=12, |k| exact_sin((k + 6) % 24))
+                .unwrap_or_else(|| Number::Approx(n.to_f64().acos()));
+            runtime.angle_mode().radians_to_angle(&radians)
+        })
+    }
+}
+
+pub struct Atan;
+
+impl Function for Atan {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |n| {
+            let radians = exact_inverse(&n, -5..=5, exact_tan)
+                .unwrap_or_else(|| Number::Approx(n.to_f64().atan()));
+            runtime.angle_mode().radians_to_angle(&radians)
+        })
+    }
+}
+
+/// Converts radians to degrees, regardless of the angle mode
+pub struct Deg;
+
+impl Function for Deg {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |radians| AngleMode::Degrees.radians_to_angle(&radians))
+    }
+}
+
+/// Converts degrees to radians, regardless of the angle mode
+pub struct Rad;
+
+impl Function for Rad {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |degrees| AngleMode::Degrees.angle_to_radians(&degrees))
+    }
+}
+
+pub struct Gamma;
+
+impl Function for Gamma {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(check_arg_count(args, 1, location)
+            .and_then(|_| number_arg(args, 0, location))
+            .and_then(|n| n.gamma(runtime.precision(), || runtime.step(location)))
+            .and_then(|result| number_result(result, runtime, location)))
+    }
+}
+
+pub struct Sqrt;
+
+impl Function for Sqrt {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        unary_function(args, runtime, location, |n| n.sqrt())
+    }
+}
+
+/// The number of elements in a list, or of characters in a string. Note that
+/// `len([x])` is `len(x)`, and the list with one element is `[x,]`
+pub struct Len;
+
+impl Function for Len {
+    fn run(&self, _: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(check_arg_count(args, 1, location)
+            .and_then(|_| match &args[0] {
+                Value::Str(string) => Ok(string.chars().count()),
+                value => value.as_list(location).map(<[Value]>::len),
+            })
+            .map(|len| Value::number(Rational64::from_integer(len as i64))))
+    }
+}
+
+/// Combines the elements of a list with `op`, starting from `initial`
+fn fold_list(runtime: &Runtime, args: &Args, location: &Location, initial: Value, op: BinaryExpr) -> Option<Result<Value>> {
+    Some(check_arg_count(args, 1, location)
+        .and_then(|_| args[0].as_list(location))
+        .and_then(|items| items.iter().try_fold(initial, |total, item| {
+            runtime.step(location)?;
+            op.eval(&total, item, runtime, location)
+        })))
+}
+
+/// Combines `body` evaluated with `index` bound to each integer from
+/// `start` to `end` inclusive, when called as `f(index, start, end, body)`
+fn fold_series(runtime: &Runtime, args: &[Expression], location: &Location, initial: Value, op: BinaryExpr) -> Option<Result<Value>> {
+    let [index, start, end, body] = args else {
+        return None;
+    };
+
+    let index = match index.content() {
+        ExpressionContent::Variable(index) => index,
+        _ => return Some(Err(Box::new(TypeError::new("index variable", "expression", *index.location())))),
+    };
+
+    let fold = || {
+        let start = runtime.eval_expr(start)?.as_integer(start.location())?;
+        let end = runtime.eval_expr(end)?.as_integer(end.location())?;
+        let mut total = initial;
+
+        for value in start..=end {
+            runtime.step(location)?;
+            let term = runtime.with_scope(index, Value::number(Rational64::from_integer(value)), location, || runtime.eval_expr(body))?;
+            total = op.eval(&total, &term, runtime, body.location())?;
+        }
+
+        Ok(total)
+    };
+
+    Some(fold())
+}
+
+/// The sum of the elements of a list, or `sum(i, start, end, body)`, the
+/// sum of `body` for each integer `i` from `start` to `end`
+pub struct Sum;
+
+impl Function for Sum {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        fold_list(runtime, args, location, Value::number(Rational64::zero()), BinaryExpr::Add)
+    }
+
+    fn run_unevaluated(&self, runtime: &Runtime, args: &[Expression], location: &Location) -> Option<Result<Value>> {
+        fold_series(runtime, args, location, Value::number(Rational64::zero()), BinaryExpr::Add)
+    }
+}
+
+/// The product of the elements of a list, or `prod(k, start, end, body)`,
+/// the product of `body` for each integer `k` from `start` to `end`
+pub struct Product;
+
+impl Function for Product {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        fold_list(runtime, args, location, Value::number(Rational64::from_integer(1)), BinaryExpr::Multiply)
+    }
+
+    fn run_unevaluated(&self, runtime: &Runtime, args: &[Expression], location: &Location) -> Option<Result<Value>> {
+        fold_series(runtime, args, location, Value::number(Rational64::from_integer(1)), BinaryExpr::Multiply)
+    }
+}
+
+/// `seq(f, n)` is the list `[f(0), f(1), ..., f(n - 1)]`
+pub struct Seq;
+
+impl Function for Seq {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(check_arg_count(args, 2, location)
+            .and_then(|_| args[1].as_count(location))
+            .and_then(|n| (0..n)
+                .map(|i| {
+                    runtime.step(location)?;
+                    runtime.call_value(&args[0], &vec![Value::number(Rational64::from_integer(i as i64))], location)
+                })
+                .collect::<Result<_>>()
+                .map(Value::List)))
+    }
+}
+
+/// Converts a value to a string, written the same way as a result
+pub struct Str;
+
+impl Function for Str {
+    fn run(&self, _: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(check_arg_count(args, 1, location).map(|_| Value::Str(args[0].to_string())))
+    }
+}
+
+/// `format(template, args...)` replaces each `{}` in the template with the
+/// next argument. A hole can instead name an argument by its number, as in
+/// `{0}`, and can end with a format spec, as in `{:.3}` or `{1:>8f}`. `{{`
+/// and `}}` are written as `{` and `}`.
+pub struct Format;
+
+impl Format {
+    fn format(template: &str, args: &[Value], location: &Location) -> Result<String> {
+        let mut result = String::new();
+        let mut next_arg = 0;
+        let mut used = vec![false; args.len()];
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    result.push('{');
+                },
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    result.push('}');
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| Box::new(FormatTemplateError::new(*location)) as Box<_>)?;
+                    let (index, spec) = match rest[..end].split_once(':') {
+                        Some((index, spec)) => (index, spec.parse::<FormatSpec>().map_err(|_| Box::new(FormatTemplateError::new(*location)) as Box<_>)?),
+                        None => (&rest[..end], FormatSpec::default()),
+                    };
+
+                    let index = if index.is_empty() {
+                        next_arg += 1;
+                        next_arg - 1
+                    }
+                    else {
+                        index.parse().map_err(|_| Box::new(FormatTemplateError::new(*location)) as Box<_>)?
+                    };
+
+                    let arg = args.get(index).ok_or_else(|| Box::new(ArgumentCountError::new(index + 2, args.len() + 1, *location)) as Box<_>)?;
+                    used[index] = true;
+                    result.push_str(&spec.apply(arg));
+                    chars = rest[end + 1..].chars();
+                },
+                '}' => return Err(Box::new(FormatTemplateError::new(*location))),
+                c => result.push(c),
+            }
+        }
+
+        // Every argument must be used, and any after the last one used are
+        // extra arguments rather than a mistake in the template
+        let expected = used.iter().rposition(|used| *used).map_or(0, |last| last + 1);
+        if expected < args.len() {
+            return Err(Box::new(ArgumentCountError::new(expected + 1, args.len() + 1, *location)));
+        }
+        if used.contains(&false) {
+            return Err(Box::new(FormatTemplateError::new(*location)));
+        }
+
+        Ok(result)
+    }
+}
+
+impl Function for Format {
+    fn run(&self, _: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(match args.split_first() {
+            Some((template, args)) => template.as_str(location)
+                .and_then(|template| Self::format(template, args, location))
+                .map(Value::Str),
+            None => Err(Box::new(ArgumentCountError::new(1, 0, *location))),
+        })
+    }
+}
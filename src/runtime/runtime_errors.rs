@@ -1,11 +1,17 @@
+use crate::number::{Number, NumberError};
 use crate::tokenize::Ident;
 use crate::LocatableContent;
 
 use super::Location;
 
 pub trait RuntimeError {
-  fn to_string(&self) -> String;
+  /// What went wrong, without where
+  fn message(&self) -> String;
   fn location(&self) -> Location;
+
+  fn to_string(&self) -> String {
+    format!("{} @ {}", self.message(), self.location())
+  }
 }
 
 pub struct ResolutionError(LocatableContent<Ident>);
@@ -17,8 +23,8 @@ impl ResolutionError {
 }
 
 impl RuntimeError for ResolutionError {
-  fn to_string(&self) -> String {
-    format!(r#"Could not resolve "{}" @ {}"#, self.0.content(), self.0.location()).into()
+  fn message(&self) -> String {
+    format!(r#"Could not resolve "{}""#, self.0.content())
   }
 
   fn location(&self) -> Location {
@@ -35,8 +41,307 @@ impl NonReturingFunctionError {
 }
 
 impl RuntimeError for NonReturingFunctionError {
-  fn to_string(&self) -> String {
-    format!(r#"Non-returning cannot be used here @ "{}"#, self.0).into()
+  fn message(&self) -> String {
+    String::from("Non-returning cannot be used here")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct ConstantAssignmentError(LocatableContent<Ident>);
+
+impl ConstantAssignmentError {
+  pub fn new(ident: LocatableContent<Ident>) -> Self {
+    Self(ident)
+  }
+}
+
+impl RuntimeError for ConstantAssignmentError {
+  fn message(&self) -> String {
+    format!(r#"Cannot assign to constant "{}""#, self.0.content())
+  }
+
+  fn location(&self) -> Location {
+      self.0.location
+  }
+}
+
+pub struct ArithmeticError(LocatableContent<NumberError>);
+
+impl ArithmeticError {
+  pub fn new(error: LocatableContent<NumberError>) -> Self {
+    Self(error)
+  }
+}
+
+impl RuntimeError for ArithmeticError {
+  fn message(&self) -> String {
+    self.0.content().to_string()
+  }
+
+  fn location(&self) -> Location {
+      self.0.location
+  }
+}
+
+pub struct ArgumentCountError {
+  expected: usize,
+  found: usize,
+  location: Location,
+}
+
+impl ArgumentCountError {
+  pub fn new(expected: usize, found: usize, location: Location) -> Self {
+    Self { expected, found, location }
+  }
+}
+
+impl RuntimeError for ArgumentCountError {
+  fn message(&self) -> String {
+    format!("Expected {} argument(s), found {}", self.expected, self.found)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct TypeError {
+  expected: &'static str,
+  found: &'static str,
+  location: Location,
+}
+
+impl TypeError {
+  pub fn new(expected: &'static str, found: &'static str, location: Location) -> Self {
+    Self { expected, found, location }
+  }
+}
+
+impl RuntimeError for TypeError {
+  fn message(&self) -> String {
+    format!("Expected {}, found {}", self.expected, self.found)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct NoMatchingClauseError(LocatableContent<Ident>);
+
+impl NoMatchingClauseError {
+  pub fn new(ident: LocatableContent<Ident>) -> Self {
+    Self(ident)
+  }
+}
+
+impl RuntimeError for NoMatchingClauseError {
+  fn message(&self) -> String {
+    format!(r#"No clause of "{}" matches its arguments"#, self.0.content())
+  }
+
+  fn location(&self) -> Location {
+      self.0.location
+  }
+}
+
+pub struct RecursionLimitError {
+  limit: usize,
+  /// Whether the limit is on expressions rather than function calls
+  expressions: bool,
+  location: Location,
+}
+
+impl RecursionLimitError {
+  pub fn new(limit: usize, location: Location) -> Self {
+    Self { limit, expressions: false, location }
+  }
+
+  pub fn expressions(limit: usize, location: Location) -> Self {
+    Self { limit, expressions: true, location }
+  }
+}
+
+impl RuntimeError for RecursionLimitError {
+  fn message(&self) -> String {
+    let nested = if self.expressions { "Expressions" } else { "Function calls" };
+    format!("{nested} nested more than {} deep", self.limit)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct OutOfFuelError(Location);
+
+impl OutOfFuelError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for OutOfFuelError {
+  fn message(&self) -> String {
+    String::from("Evaluation ran out of fuel")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct DeadlineExceededError(Location);
+
+impl DeadlineExceededError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for DeadlineExceededError {
+  fn message(&self) -> String {
+    String::from("Evaluation took too long")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct CancelledError(Location);
+
+impl CancelledError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for CancelledError {
+  fn message(&self) -> String {
+    String::from("Evaluation was cancelled")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct ShapeError {
+  left: usize,
+  right: usize,
+  location: Location,
+}
+
+impl ShapeError {
+  pub fn new(left: usize, right: usize, location: Location) -> Self {
+    Self { left, right, location }
+  }
+}
+
+impl RuntimeError for ShapeError {
+  fn message(&self) -> String {
+    format!("Cannot combine lists of length {} and {}", self.left, self.right)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct IndexError {
+  index: Number,
+  len: usize,
+  location: Location,
+}
+
+impl IndexError {
+  pub fn new(index: Number, len: usize, location: Location) -> Self {
+    Self { index, len, location }
+  }
+}
+
+impl RuntimeError for IndexError {
+  fn message(&self) -> String {
+    format!("Index {} is not valid for a list of length {}", self.index, self.len)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct ZeroStepError(Location);
+
+impl ZeroStepError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for ZeroStepError {
+  fn message(&self) -> String {
+    String::from("The step of a range must be a non-zero number")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct InfiniteRangeError(Location);
+
+impl InfiniteRangeError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for InfiniteRangeError {
+  fn message(&self) -> String {
+    String::from("The bounds of a range must be finite")
+  }
+
+  fn location(&self) -> Location {
+      self.0
+  }
+}
+
+pub struct RangeTooLongError {
+  limit: usize,
+  location: Location,
+}
+
+impl RangeTooLongError {
+  pub fn new(limit: usize, location: Location) -> Self {
+    Self { limit, location }
+  }
+}
+
+impl RuntimeError for RangeTooLongError {
+  fn message(&self) -> String {
+    format!("Range has more than {} elements", self.limit)
+  }
+
+  fn location(&self) -> Location {
+      self.location
+  }
+}
+
+pub struct FormatTemplateError(Location);
+
+impl FormatTemplateError {
+  pub fn new(location: Location) -> Self {
+    Self(location)
+  }
+}
+
+impl RuntimeError for FormatTemplateError {
+  fn message(&self) -> String {
+    String::from("Invalid format template")
   }
 
   fn location(&self) -> Location {
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::{Function, Runtime, Result};
+use super::runtime_errors::{ArgumentCountError, NoMatchingClauseError};
+use crate::ast::{Args, Clause, Expression, ExpressionContent, Value};
+use crate::tokenize::Ident;
+use crate::{LocatableContent, Location};
+
+/// A function defined in calculator code, e.g. `f(x) = x^2`
+pub struct UserFunction {
+    name: Ident,
+    params: Vec<Ident>,
+    clauses: Vec<Clause>,
+}
+
+/// The result of evaluating an expression in tail position
+enum Evaluated {
+    Value(Value),
+    /// A call to a user-defined function, which is left to the caller so
+    /// that it does not grow the stack
+    TailCall(Rc<dyn Function>, Args, Location),
+}
+
+impl UserFunction {
+    pub fn new(name: Ident, params: Vec<Ident>, clauses: Vec<Clause>) -> Self {
+        Self { name, params, clauses }
+    }
+
+    pub fn params(&self) -> &[Ident] {
+        &self.params
+    }
+
+    pub fn clauses(&self) -> &[Clause] {
+        &self.clauses
+    }
+
+    fn call(&self, runtime: &Runtime, args: &Args, location: &Location) -> Result<Evaluated> {
+        if args.len() != self.params.len() {
+            return Err(Box::new(ArgumentCountError::new(self.params.len(), args.len(), *location)));
+        }
+
+        let frame = self.params.iter().cloned().zip(args.iter().cloned()).collect::<HashMap<_, _>>();
+        runtime.with_frame(frame, || self.eval(runtime, location))
+    }
+
+    /// Evaluates the body of the first clause whose guard is true
+    fn eval(&self, runtime: &Runtime, location: &Location) -> Result<Evaluated> {
+        for (body, guard) in self.clauses.iter() {
+            let matches = match guard {
+                Some(guard) => runtime.eval_expr(guard)?.as_bool(guard.location())?,
+                None => true,
+            };
+
+            if matches {
+                return eval_tail(runtime, body);
+            }
+        }
+
+        Err(Box::new(NoMatchingClauseError::new(LocatableContent::new(self.name.clone(), *location))))
+    }
+}
+
+/// Evaluates an expression whose value is returned directly from a function.
+/// Brackets and conditionals are stepped into in a loop, so they don't use
+/// any stack.
+fn eval_tail(runtime: &Runtime, mut expr: &Expression) -> Result<Evaluated> {
+    use ExpressionContent::*;
+
+    loop {
+        expr = match expr.content() {
+            Parenthases(content) => content,
+            Conditional(branches) => {
+                let [condition, then, otherwise] = &**branches;
+                if runtime.eval_expr(condition)?.as_bool(condition.location())? { then } else { otherwise }
+            },
+            _ => break,
+        };
+    }
+
+    match expr.content() {
+        ExecuteFn((f, raw_args)) => {
+            let function = match runtime.resolve_function(f, expr.location()) {
+                Ok(function) if function.as_user_function().is_some() => function,
+                _ => return runtime.eval_expr(expr).map(Evaluated::Value),
+            };
+
+            let args = raw_args.iter().map(|arg| runtime.eval_expr(arg)).collect::<Result<Args>>()?;
+            Ok(Evaluated::TailCall(function, args, *expr.location()))
+        },
+        _ => runtime.eval_expr(expr).map(Evaluated::Value),
+    }
+}
+
+impl Function for UserFunction {
+    fn run(&self, runtime: &Runtime, args: &Args, location: &Location) -> Option<Result<Value>> {
+        Some(runtime.enter_call(location, || {
+            let mut evaluated = self.call(runtime, args, location)?;
+
+            loop {
+                match evaluated {
+                    Evaluated::Value(value) => return Ok(value),
+                    Evaluated::TailCall(function, args, location) => {
+                        let function = function.as_user_function().unwrap();
+                        evaluated = function.call(runtime, &args, &location)?;
+                    },
+                }
+            }
+        }))
+    }
+
+    fn as_user_function(&self) -> Option<&UserFunction> {
+        Some(self)
+    }
+}
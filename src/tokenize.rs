@@ -1,6 +1,9 @@
-use std::{cell::RefCell, collections::HashMap, error::Error, fmt::{self, Display, Formatter}, iter::Peekable, num::ParseIntError, sync::{atomic::{AtomicUsize, Ordering}, Mutex, PoisonError, RwLock}, thread::{self, ThreadId}};
-use crate::{error::*, LocatableContent, Location};
+pub mod lossless;
+
+use std::iter::Peekable;
+use crate::{error::*, format_spec::{FormatSpec, FormatSpecError}, LocatableContent, Location, MAX_DEPTH};
 use num_rational::Rational64;
+use num_traits::CheckedAdd;
 
 struct UnprocessedToken<'a>(&'a str);
 
@@ -22,6 +25,9 @@ pub enum TokenContent {
 
     Block(DelimiterType, TokenStream),
 
+    /// A line break outside of any delimiters, which ends a statement
+    Newline,
+
     EOF
 }
 
@@ -52,23 +58,47 @@ impl TryFrom<char> for Delimiter {
         use DelimiterType::*;
         use DelimiterDirection::*;
 
-        match ch {
-            '(' => Ok(Self {
-                ty: Parenthases,
-                direction: Open,
-            }),
-            ')' => Ok(Self {
-                ty: Parenthases,
-                direction: Close,
-            }),
-            _ => Err(())
-        }
+        let (ty, direction) = match ch {
+            '(' => (Parenthases, Open),
+            ')' => (Parenthases, Close),
+            '[' => (Bracket, Open),
+            ']' => (Bracket, Close),
+            '{' => (Brace, Open),
+            '}' => (Brace, Close),
+            _ => return Err(())
+        };
+
+        Ok(Self { ty, direction })
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum DelimiterType {
-    Parenthases
+    /// `(` and `)`
+    Parenthases,
+    /// `[` and `]`, which make a list when they hold a comma or nothing, and
+    /// group like `(` and `)` otherwise
+    Bracket,
+    /// `{` and `}`
+    Brace,
+}
+
+impl DelimiterType {
+    pub fn open(&self) -> &'static str {
+        match self {
+            DelimiterType::Parenthases => "(",
+            DelimiterType::Bracket => "[",
+            DelimiterType::Brace => "{",
+        }
+    }
+
+    pub fn close(&self) -> &'static str {
+        match self {
+            DelimiterType::Parenthases => ")",
+            DelimiterType::Bracket => "]",
+            DelimiterType::Brace => "}",
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
@@ -80,6 +110,16 @@ pub enum DelimiterDirection {
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub enum Value {
     Number(Rational64),
+    Str(String),
+    /// A string literal containing `{expression}` holes. Unlike in other
+    /// strings, `{{` and `}}` in its text are literal braces.
+    Interpolated(Vec<StrPart>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum StrPart {
+    Text(String),
+    Hole(TokenStream, Option<FormatSpec>),
 }
 
 // impl TryInto<Ident> for Token {
@@ -104,7 +144,26 @@ pub enum Punct {
     Dash,
     Asterisk,
     Slash,
+    Percent,
+    Caret,
+    Equals,
+    DoubleEquals,
+    BangEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
     Comma,
+    Semicolon,
+    Degree,
+    Bang,
+    DoubleBang,
+    DoubleAmpersand,
+    DoublePipe,
+    Question,
+    Colon,
+    DoubleDot,
+    DoubleDotEquals,
     // Period,
 }
 
@@ -119,7 +178,26 @@ impl <'a> TryFrom<UnprocessedToken<'a>> for Punct {
             "-" => Ok(Dash),
             "*" => Ok(Asterisk),
             "/" => Ok(Slash),
+            "%" => Ok(Percent),
+            "^" => Ok(Caret),
+            "=" => Ok(Equals),
+            "==" => Ok(DoubleEquals),
+            "!=" => Ok(BangEquals),
+            "<" => Ok(Less),
+            "<=" => Ok(LessEquals),
+            ">" => Ok(Greater),
+            ">=" => Ok(GreaterEquals),
             "," => Ok(Comma),
+            ";" => Ok(Semicolon),
+            "°" => Ok(Degree),
+            "!" => Ok(Bang),
+            "!!" => Ok(DoubleBang),
+            "&&" => Ok(DoubleAmpersand),
+            "||" => Ok(DoublePipe),
+            "?" => Ok(Question),
+            ":" => Ok(Colon),
+            ".." => Ok(DoubleDot),
+            "..=" => Ok(DoubleDotEquals),
             // "." => Ok(Period),
             _ => Err(NoSuchPunct),
         }
@@ -153,6 +231,41 @@ impl Tokenizer {
         self.tokenize_inner(stream).map_err(|kind| self.error(kind))
     }
 
+    /// Finishes a range operator once `..` has been consumed, which is
+    /// `..=` if it is followed by `=`
+    fn range_punct(&mut self, stream: &mut Peekable<impl Iterator<Item = char>>) -> Punct {
+        if stream.peek() == Some(&'=') {
+            stream.next();
+            self.location.len = 3;
+            Punct::DoubleDotEquals
+        }
+        else {
+            self.location.len = 2;
+            Punct::DoubleDot
+        }
+    }
+
+    /// Tokenizes the expression in a hole of an interpolated string, which
+    /// starts at `begin`. Holes with no expression or just an argument
+    /// number, like `{}` or `{1:.2}`, are left in the string for `format`,
+    /// which checks their spec.
+    fn tokenize_hole(&mut self, hole: &str, begin: usize) -> Result<Option<StrPart>, CompilationErrorKind> {
+        let argument = hole.split(':').next().unwrap_or_default();
+        if argument.trim().chars().all(|c| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+
+        let (expr, spec) = split_format_spec(hole).map_err(|_| CompilationErrorKind::FormatSpecTooLarge)?;
+        let mut tokenizer = Tokenizer { location: Location { begin, len: 0 } };
+        match tokenizer.tokenize_inner(expr.chars().peekable()) {
+            Ok(tokens) => Ok(Some(StrPart::Hole(tokens, spec))),
+            Err(kind) => {
+                self.location = tokenizer.location;
+                Err(kind)
+            },
+        }
+    }
+
     fn tokenize_inner(&mut self, mut stream: Peekable<impl Iterator<Item = char>>) -> Result<TokenStream, CompilationErrorKind> {
         let mut tokens = Vec::new();
         let mut blocks = Vec::new();
@@ -161,10 +274,12 @@ impl Tokenizer {
         while let Some(c) = stream.next() {
             self.location.len = 1;
 
-            if c.is_numeric() || c == '.' && stream.peek().map_or(false, |c| c.is_numeric()) {
+            if c.is_numeric() || c == '.' && stream.peek().is_some_and(|c| c.is_numeric()) {
                 let mut num_str = String::from(c);
 
                 let mut point_has_passed = c == '.';
+                // Whether the number is followed by `..`, as in `1..10`
+                let mut range_follows = false;
 
                 while let Some(n) = stream.peek() {
                     self.location.len += 1;
@@ -173,11 +288,18 @@ impl Tokenizer {
                         num_str.push(stream.next().unwrap());
                     }
                     else if n == &'.' {
-                        if point_has_passed {
+                        stream.next();
+                        if stream.peek() == Some(&'.') {
+                            stream.next();
+                            self.location.len -= 1;
+                            range_follows = true;
+                            break;
+                        }
+                        else if point_has_passed {
                             return Err(CompilationErrorKind::TwoDecimalPoints);
                         }
                         else {
-                            num_str.push(stream.next().unwrap());
+                            num_str.push('.');
                             point_has_passed = true;
                         }
                     }
@@ -194,16 +316,17 @@ impl Tokenizer {
                     // The reason I don't just use Ratio::from_float(s.parse().unwrap()) is because of roundoff errors.
                     // For example, Ratio::from_float(0.3).unwrap() = 5,404,319,552,844,595/18,014,398,509,481,984
                     Some(index) => {
-                        ( if index == 0 { Rational64::from_integer(0) }
-                            else { Rational64::from_integer((&num_str[0..index]).parse().unwrap()) } )
-                        + ( if index == num_str.len() - 1 { Rational64::from_integer(0) } 
-                            else { Rational64::new
-                                ((&num_str[index + 1..num_str.len()]).parse().unwrap(), 
-                                10i64.pow((num_str.len() - index - 1).try_into().unwrap()) ) }
-                        )
+                        let whole = if index == 0 { Some(0) } else { num_str[0..index].parse().ok() };
+                        let fraction = if index == num_str.len() - 1 { Some(Rational64::from_integer(0)) }
+                            else {
+                                let digits = &num_str[index + 1..num_str.len()];
+                                digits.parse().ok().zip(10i64.checked_pow(digits.len() as u32))
+                                    .map(|(numer, denom)| Rational64::new(numer, denom))
+                            };
+                        whole.zip(fraction).and_then(|(whole, fraction)| Rational64::from_integer(whole).checked_add(&fraction))
                     },
-                    None => Rational64::from_integer(num_str.parse().unwrap()),
-                };
+                    None => num_str.parse().ok().map(Rational64::from_integer),
+                }.ok_or(CompilationErrorKind::TooPreciseNumber)?;
 
 
                 // let value = u64::from_str_radix(&num_str, 10)
@@ -218,6 +341,67 @@ impl Tokenizer {
                 });
 
                 self.location.begin += num_str.len();
+
+                if range_follows {
+                    let punct = self.range_punct(&mut stream);
+                    tokens.push(Token::new(TokenContent::Punct(punct), self.location));
+                    self.location.begin += self.location.len;
+                }
+            }
+            // `#` or `//` starts a comment that runs to the end of the line
+            else if c == '#' || c == '/' && stream.peek() == Some(&'/') {
+                let mut comment = String::from(c);
+                while let Some(n) = stream.next_if(|n| n != &'\n') {
+                    comment.push(n);
+                    self.location.len += 1;
+                }
+
+                // `//` used to be floor division, so `7 // 2` is more likely
+                // to mean that than to be `7` with a comment
+                let follows_operand = tokens.last().is_some_and(|token: &Token| matches!(
+                    token.content(),
+                    TokenContent::Value(_) | TokenContent::Ident(_) | TokenContent::Block(..)
+                        | TokenContent::Punct(Punct::Bang | Punct::DoubleBang | Punct::Degree)
+                ));
+                let starts_operand = comment.strip_prefix("//")
+                    .and_then(|rest| rest.trim_start().chars().next())
+                    .is_some_and(|n| n.is_ascii_digit() || n == '(');
+                if follows_operand && starts_operand {
+                    return Err(CompilationErrorKind::FloorDivisionComment);
+                }
+
+                self.location.begin += self.location.len;
+            }
+            else if c == '/' && stream.peek() == Some(&'*') {
+                stream.next();
+                self.location.len += 1;
+
+                let mut previous = None;
+                loop {
+                    let n = stream.next().ok_or(CompilationErrorKind::UnterminatedComment)?;
+                    self.location.len += 1;
+                    if previous == Some('*') && n == '/' {
+                        break;
+                    }
+                    previous = Some(n);
+                }
+
+                self.location.begin += self.location.len;
+            }
+            else if c == '.' && stream.peek() == Some(&'.') {
+                stream.next();
+                let punct = self.range_punct(&mut stream);
+                tokens.push(Token::new(TokenContent::Punct(punct), self.location));
+                self.location.begin += self.location.len;
+            }
+            else if let Some(punct) = stream.peek()
+                .and_then(|n| Punct::try_from(UnprocessedToken(&format!("{c}{n}"))).ok())
+            {
+                stream.next();
+                self.location.len = 2;
+                tokens.push(Token::new(TokenContent::Punct(punct), self.location));
+
+                self.location.begin += 2;
             }
             else if let Ok(punct) = UnprocessedToken(&String::from(c)).try_into()
             {
@@ -230,6 +414,9 @@ impl Tokenizer {
             }
             else if let Ok(delim) = Delimiter::try_from(c) {
                 if delim.direction() == &DelimiterDirection::Open {
+                    if delims.len() == MAX_DEPTH {
+                        return Err(CompilationErrorKind::TooDeeplyNested);
+                    }
                     blocks.push(tokens);
                     tokens = Vec::new();
                     delims.push(LocatableContent::new(delim, self.location));
@@ -252,14 +439,106 @@ impl Tokenizer {
 
                 self.location.begin += 1;
             }
+            else if c == '\n' && delims.is_empty() {
+                tokens.push(Token::new(TokenContent::Newline, self.location));
+                self.location.begin += 1;
+            }
             else if c.is_whitespace() {
                 self.location.begin += 1;
             }
-            else if c.is_ascii_alphabetic() {
+            else if c == '"' {
+                let mut parts = Vec::new();
+                // The text of the string if it has no holes, where `{{` and
+                // `}}` are kept for `format`, and if it has, where they are
+                // literal braces
+                let mut string = String::new();
+                let mut text = String::new();
+
+                loop {
+                    let n = stream.next().ok_or(CompilationErrorKind::UnterminatedString)?;
+                    self.location.len += 1;
+
+                    match n {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = stream.next().ok_or(CompilationErrorKind::UnterminatedString)?;
+                            self.location.len += 1;
+                            let escaped = match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                'r' => '\r',
+                                '0' => '\0',
+                                '\\' | '"' | '{' | '}' => escaped,
+                                _ => return Err(CompilationErrorKind::InvalidEscape),
+                            };
+                            string.push(escaped);
+                            text.push(escaped);
+                        },
+                        '{' | '}' if stream.peek() == Some(&n) => {
+                            stream.next();
+                            self.location.len += 1;
+                            string.extend([n, n]);
+                            text.push(n);
+                        },
+                        '{' => {
+                            let hole_begin = self.location.begin + self.location.len;
+                            let mut hole = String::new();
+                            let mut depth = 0;
+
+                            loop {
+                                let h = match stream.next() {
+                                    Some('"') | None => return Err(CompilationErrorKind::UnterminatedInterpolation),
+                                    Some(h) => h,
+                                };
+                                self.location.len += 1;
+
+                                match h {
+                                    '}' if depth == 0 => break,
+                                    '}' => depth -= 1,
+                                    '{' => depth += 1,
+                                    _ => {},
+                                }
+                                hole.push(h);
+                            }
+
+                            match self.tokenize_hole(&hole, hole_begin)? {
+                                Some(part) => {
+                                    if !text.is_empty() {
+                                        parts.push(StrPart::Text(std::mem::take(&mut text)));
+                                    }
+                                    parts.push(part);
+                                },
+                                None => {
+                                    string.push_str(&format!("{{{hole}}}"));
+                                    text.push_str(&format!("{{{hole}}}"));
+                                },
+                            }
+                        },
+                        _ => {
+                            string.push(n);
+                            text.push(n);
+                        },
+                    }
+                }
+
+                let value = if parts.is_empty() {
+                    Value::Str(string)
+                }
+                else {
+                    if !text.is_empty() {
+                        parts.push(StrPart::Text(text));
+                    }
+                    Value::Interpolated(parts)
+                };
+
+                tokens.push(Token::new(TokenContent::Value(value), self.location));
+                self.location.begin += self.location.len;
+            }
+            else if c.is_alphabetic() || c == '_' {
                 let mut word = String::from(c);
 
                 while let Some(n) = stream.peek() {
-                    if n.is_ascii_alphabetic() {
+                    if n.is_alphanumeric() || n == &'_' {
                         word.push(stream.next().unwrap());
                         self.location.len += 1;
                     }
@@ -268,7 +547,8 @@ impl Tokenizer {
                     }
                 } 
 
-                
+                tokens.push(Token::new(TokenContent::Ident(word), self.location));
+                self.location.begin += self.location.len;
             }
             else {
                 return Err(CompilationErrorKind::UnrecognizedCharacter);
@@ -290,5 +570,29 @@ impl Tokenizer {
 
 pub type Ident = String;
 
+/// Splits the format spec from the end of a hole at its last `:` outside of
+/// any brackets, if what follows is a valid spec. A conditional such as
+/// `{a ? b : 1}` needs brackets to not be read as having a spec. Fails if
+/// what follows is a spec, but one over the size limits.
+pub fn split_format_spec(hole: &str) -> Result<(&str, Option<FormatSpec>), FormatSpecError> {
+    let mut depth = 0i32;
+    let mut colon = None;
+
+    for (i, c) in hole.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ':' if depth == 0 => colon = Some(i),
+            _ => {},
+        }
+    }
+
+    match colon.map(|i| (i, hole[i + 1..].parse())) {
+        Some((i, Ok(spec))) => Ok((&hole[..i], Some(spec))),
+        Some((_, Err(FormatSpecError::TooLarge))) => Err(FormatSpecError::TooLarge),
+        Some((_, Err(FormatSpecError::Invalid))) | None => Ok((hole, None)),
+    }
+}
+
 
 
@@ -0,0 +1,169 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::{tokenize, Delimiter, DelimiterDirection, Token, TokenContent};
+use crate::error::CompilationError;
+use crate::{LocatableContent, Location};
+
+/// A token from a lossless token stream. Blocks are split into their opening
+/// delimiter, their contents and their closing delimiter, so the stream is
+/// flat.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum LosslessContent {
+    /// Any token other than a block
+    Token(TokenContent),
+    Delimiter(Delimiter),
+}
+
+/// Source text that doesn't affect the meaning of the code
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum TriviaContent {
+    Whitespace(String),
+    /// `# ...` or `// ...`, not including the line break
+    LineComment(String),
+    /// `/* ... */`
+    BlockComment(String),
+}
+
+pub type Trivia = LocatableContent<TriviaContent>;
+
+impl TriviaContent {
+    pub fn text(&self) -> &str {
+        match self {
+            TriviaContent::Whitespace(text)
+            | TriviaContent::LineComment(text)
+            | TriviaContent::BlockComment(text) => text,
+        }
+    }
+}
+
+/// A token along with its source text and the trivia around it. A token owns
+/// the trivia after it up to the end of its line, and the next token owns
+/// the rest. Line breaks that end a statement are tokens themselves, and
+/// have no trivia after them.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct LosslessToken {
+    content: LosslessContent,
+    location: Location,
+    text: String,
+    leading: Vec<Trivia>,
+    trailing: Vec<Trivia>,
+}
+
+impl LosslessToken {
+    pub fn content(&self) -> &LosslessContent {
+        &self.content
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// The source text of the token itself, without any trivia
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn leading(&self) -> &[Trivia] {
+        &self.leading
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.trailing
+    }
+}
+
+/// Writes the token exactly as it was in the source, trivia included
+impl Display for LosslessToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading {
+            write!(f, "{}", trivia.content().text())?;
+        }
+        write!(f, "{}", self.text)?;
+        for trivia in &self.trailing {
+            write!(f, "{}", trivia.content().text())?;
+        }
+        Ok(())
+    }
+}
+
+/// Tokenizes `source` keeping all of its whitespace and comments, so that
+/// writing out every token reproduces `source` exactly. The stream always
+/// ends with an `EOF` token, which owns any trivia at the end of the source.
+pub fn tokenize_lossless(source: &str) -> Result<Vec<LosslessToken>, CompilationError> {
+    let chars = source.chars().collect::<Vec<_>>();
+
+    let mut flat = Vec::new();
+    flatten(tokenize(chars.iter().copied())?, &mut flat);
+
+    let mut tokens = Vec::<LosslessToken>::new();
+    let mut position = 0;
+
+    for (content, location) in flat {
+        let mut trivia = split_trivia(&chars, position..location.begin()).into_iter().peekable();
+
+        // Trivia up to the end of the line belongs to the previous token,
+        // unless that token is the end of the line
+        let previous = tokens.last_mut()
+            .filter(|previous| previous.content != LosslessContent::Token(TokenContent::Newline));
+        if let Some(previous) = previous {
+            while let Some(next) = trivia.next_if(|trivia| !trivia.content().text().starts_with('\n')) {
+                previous.trailing.push(next);
+            }
+        }
+
+        tokens.push(LosslessToken {
+            content,
+            location,
+            text: chars[location.begin()..location.end()].iter().collect(),
+            leading: trivia.collect(),
+            trailing: Vec::new(),
+        });
+        position = location.end();
+    }
+
+    Ok(tokens)
+}
+
+/// Flattens a token stream, replacing each block with its delimiters and
+/// contents
+fn flatten(stream: Vec<Token>, flat: &mut Vec<(LosslessContent, Location)>) {
+    for token in stream {
+        let location = *token.location();
+        match token.content {
+            TokenContent::Block(ty, inner) => {
+                flat.push((LosslessContent::Delimiter(Delimiter::new(ty, DelimiterDirection::Open)), (location.begin()..location.begin() + 1).into()));
+                flatten(inner, flat);
+                flat.push((LosslessContent::Delimiter(Delimiter::new(ty, DelimiterDirection::Close)), (location.end() - 1..location.end()).into()));
+            },
+            content => flat.push((LosslessContent::Token(content), location)),
+        }
+    }
+}
+
+/// Splits the text between two tokens into whitespace and comments. Runs of
+/// whitespace are split before each line break.
+fn split_trivia(chars: &[char], range: std::ops::Range<usize>) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut position = range.start;
+
+    while position < range.end {
+        let rest = &chars[position..range.end];
+        let len = match rest {
+            ['#', ..] | ['/', '/', ..] => rest.iter().position(|c| c == &'\n').unwrap_or(rest.len()),
+            // The `*/` can't overlap the `/*`, so `/*/` isn't a whole comment
+            ['/', '*', ..] => rest.windows(2).skip(2).position(|pair| pair == ['*', '/']).map_or(rest.len(), |i| i + 4),
+            _ => 1 + rest[1..].iter().position(|c| !c.is_whitespace() || c == &'\n').unwrap_or(rest.len() - 1),
+        };
+
+        let text = rest[..len].iter().collect::<String>();
+        let content = match rest {
+            ['#', ..] | ['/', '/', ..] => TriviaContent::LineComment(text),
+            ['/', '*', ..] => TriviaContent::BlockComment(text),
+            _ => TriviaContent::Whitespace(text),
+        };
+        trivia.push(Trivia::new(content, (position..position + len).into()));
+        position += len;
+    }
+
+    trivia
+}
@@ -0,0 +1,211 @@
+use crate::ast::{Clause, Code, CodeObject, CodeObjectContent, Expression, ExpressionContent, InterpolatedPart};
+
+/// Visits a syntax tree by reference. There is a method for every kind of
+/// node, which by default calls the matching `walk_*` function to visit the
+/// node's children. Override a method to act on that kind of node, and call
+/// the walk function from it to keep going deeper.
+pub trait Visitor {
+    fn visit_code(&mut self, code: &Code) {
+        walk_code(self, code)
+    }
+
+    fn visit_code_object(&mut self, statement: &CodeObject) {
+        walk_code_object(self, statement)
+    }
+
+    fn visit_clause(&mut self, clause: &Clause) {
+        walk_clause(self, clause)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+}
+
+pub fn walk_code<V: Visitor + ?Sized>(visitor: &mut V, code: &Code) {
+    for statement in code {
+        visitor.visit_code_object(statement);
+    }
+}
+
+pub fn walk_code_object<V: Visitor + ?Sized>(visitor: &mut V, statement: &CodeObject) {
+    match statement.content() {
+        CodeObjectContent::ExecuteFn((_, args)) => args.iter().for_each(|arg| visitor.visit_expression(arg)),
+        CodeObjectContent::Expression(expr) | CodeObjectContent::Assign(_, expr) => visitor.visit_expression(expr),
+        CodeObjectContent::DefineFn(_, _, clauses) => clauses.iter().for_each(|clause| visitor.visit_clause(clause)),
+    }
+}
+
+pub fn walk_clause<V: Visitor + ?Sized>(visitor: &mut V, (body, guard): &Clause) {
+    visitor.visit_expression(body);
+    if let Some(guard) = guard {
+        visitor.visit_expression(guard);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    use ExpressionContent::*;
+
+    match expr.content() {
+        ExecuteFn((_, args)) | List(args) => args.iter().for_each(|arg| visitor.visit_expression(arg)),
+        Parenthases(inner) | UnaryExpr(_, inner) => visitor.visit_expression(inner),
+        Literal(_) | Str(_) | Variable(_) => {},
+        Interpolated(parts) => {
+            for part in parts {
+                if let InterpolatedPart::Hole(expr, _) = part {
+                    visitor.visit_expression(expr);
+                }
+            }
+        },
+        BinaryExpr(_, args) | Index(args) => args.iter().for_each(|arg| visitor.visit_expression(arg)),
+        Conditional(branches) => branches.iter().for_each(|branch| visitor.visit_expression(branch)),
+        Range { bounds, step, .. } => {
+            bounds.iter().for_each(|bound| visitor.visit_expression(bound));
+            if let Some(step) = step {
+                visitor.visit_expression(step);
+            }
+        },
+    }
+}
+
+/// Like `Visitor`, but by mutable reference, to change the tree in place
+pub trait VisitorMut {
+    fn visit_code_mut(&mut self, code: &mut Code) {
+        walk_code_mut(self, code)
+    }
+
+    fn visit_code_object_mut(&mut self, statement: &mut CodeObject) {
+        walk_code_object_mut(self, statement)
+    }
+
+    fn visit_clause_mut(&mut self, clause: &mut Clause) {
+        walk_clause_mut(self, clause)
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+}
+
+pub fn walk_code_mut<V: VisitorMut + ?Sized>(visitor: &mut V, code: &mut Code) {
+    for statement in code {
+        visitor.visit_code_object_mut(statement);
+    }
+}
+
+pub fn walk_code_object_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut CodeObject) {
+    match statement.content_mut() {
+        CodeObjectContent::ExecuteFn((_, args)) => args.iter_mut().for_each(|arg| visitor.visit_expression_mut(arg)),
+        CodeObjectContent::Expression(expr) | CodeObjectContent::Assign(_, expr) => visitor.visit_expression_mut(expr),
+        CodeObjectContent::DefineFn(_, _, clauses) => clauses.iter_mut().for_each(|clause| visitor.visit_clause_mut(clause)),
+    }
+}
+
+pub fn walk_clause_mut<V: VisitorMut + ?Sized>(visitor: &mut V, (body, guard): &mut Clause) {
+    visitor.visit_expression_mut(body);
+    if let Some(guard) = guard {
+        visitor.visit_expression_mut(guard);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    use ExpressionContent::*;
+
+    match expr.content_mut() {
+        ExecuteFn((_, args)) | List(args) => args.iter_mut().for_each(|arg| visitor.visit_expression_mut(arg)),
+        Parenthases(inner) | UnaryExpr(_, inner) => visitor.visit_expression_mut(inner),
+        Literal(_) | Str(_) | Variable(_) => {},
+        Interpolated(parts) => {
+            for part in parts {
+                if let InterpolatedPart::Hole(expr, _) = part {
+                    visitor.visit_expression_mut(expr);
+                }
+            }
+        },
+        BinaryExpr(_, args) | Index(args) => args.iter_mut().for_each(|arg| visitor.visit_expression_mut(arg)),
+        Conditional(branches) => branches.iter_mut().for_each(|branch| visitor.visit_expression_mut(branch)),
+        Range { bounds, step, .. } => {
+            bounds.iter_mut().for_each(|bound| visitor.visit_expression_mut(bound));
+            if let Some(step) = step {
+                visitor.visit_expression_mut(step);
+            }
+        },
+    }
+}
+
+/// Rebuilds a syntax tree by value, so nodes can be replaced with nodes of a
+/// different kind, e.g. `x * 1` with `x`. The default methods call the
+/// matching `fold_*` functions, which rebuild the node from its folded
+/// children.
+pub trait Fold {
+    fn fold_code(&mut self, code: Code) -> Code {
+        fold_code(self, code)
+    }
+
+    fn fold_code_object(&mut self, statement: CodeObject) -> CodeObject {
+        fold_code_object(self, statement)
+    }
+
+    fn fold_clause(&mut self, clause: Clause) -> Clause {
+        fold_clause(self, clause)
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_expression(self, expr)
+    }
+}
+
+pub fn fold_code<F: Fold + ?Sized>(folder: &mut F, code: Code) -> Code {
+    code.into_iter().map(|statement| folder.fold_code_object(statement)).collect()
+}
+
+pub fn fold_code_object<F: Fold + ?Sized>(folder: &mut F, statement: CodeObject) -> CodeObject {
+    let location = *statement.location();
+    let content = match statement.into_content() {
+        CodeObjectContent::ExecuteFn((f, args)) => CodeObjectContent::ExecuteFn((f, fold_all(folder, args))),
+        CodeObjectContent::Expression(expr) => CodeObjectContent::Expression(folder.fold_expression(expr)),
+        CodeObjectContent::Assign(ident, expr) => CodeObjectContent::Assign(ident, folder.fold_expression(expr)),
+        CodeObjectContent::DefineFn(ident, params, clauses) => {
+            CodeObjectContent::DefineFn(ident, params, clauses.into_iter().map(|clause| folder.fold_clause(clause)).collect())
+        },
+    };
+    CodeObject::new(content, location)
+}
+
+pub fn fold_clause<F: Fold + ?Sized>(folder: &mut F, (body, guard): Clause) -> Clause {
+    (folder.fold_expression(body), guard.map(|guard| folder.fold_expression(guard)))
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(folder: &mut F, expr: Expression) -> Expression {
+    use ExpressionContent::*;
+
+    let location = *expr.location();
+    let content = match expr.into_content() {
+        ExecuteFn((f, args)) => ExecuteFn((f, fold_all(folder, args))),
+        Parenthases(inner) => Parenthases(Box::new(folder.fold_expression(*inner))),
+        content @ (Literal(_) | Str(_) | Variable(_)) => content,
+        Interpolated(parts) => Interpolated(
+            parts.into_iter()
+                .map(|part| match part {
+                    InterpolatedPart::Hole(expr, spec) => InterpolatedPart::Hole(folder.fold_expression(expr), spec),
+                    text => text,
+                })
+                .collect(),
+        ),
+        UnaryExpr(op, operand) => UnaryExpr(op, Box::new(folder.fold_expression(*operand))),
+        BinaryExpr(op, args) => BinaryExpr(op, Box::new(args.map(|arg| folder.fold_expression(arg)))),
+        Conditional(branches) => Conditional(Box::new(branches.map(|branch| folder.fold_expression(branch)))),
+        List(elements) => List(fold_all(folder, elements)),
+        Index(args) => Index(Box::new(args.map(|arg| folder.fold_expression(arg)))),
+        Range { bounds, step, inclusive } => Range {
+            bounds: Box::new(bounds.map(|bound| folder.fold_expression(bound))),
+            step: step.map(|step| Box::new(folder.fold_expression(*step))),
+            inclusive,
+        },
+    };
+    Expression::new(content, location)
+}
+
+fn fold_all<F: Fold + ?Sized>(folder: &mut F, exprs: Vec<Expression>) -> Vec<Expression> {
+    exprs.into_iter().map(|expr| folder.fold_expression(expr)).collect()
+}
@@ -0,0 +1,53 @@
+use simple_calculator::formatter::format;
+
+fn formatted(source: &str) -> String {
+    format(source).unwrap_or_else(|e| panic!("{source:?}: {e}"))
+}
+
+#[test]
+fn canonical_style() {
+    for (source, expected) in [
+        ("y=1+2*3", "y = 1 + 2 * 3\n"),
+        ("y = ((1+2))*3", "y = (1 + 2) * 3\n"),
+        ("y = 1-(2-3)", "y = 1 - (2 - 3)\n"),
+        ("y = (1-2)-3", "y = 1 - 2 - 3\n"),
+        ("y = 2^(3^2)", "y = 2 ^ 3 ^ 2\n"),
+        ("f(x)=x^2 when x>0;0 otherwise", "f(x) = x ^ 2 when x > 0; 0 otherwise\n"),
+        ("x=1;y=2", "x = 1\ny = 2\n"),
+        ("y = [1,2,\n3]", "y = [1, 2, 3]\n"),
+        ("\n\n\nx=1\n\n\n\ny=2\n\n", "x = 1\n\ny = 2\n"),
+    ] {
+        assert_eq!(formatted(source), expected, "{source:?}");
+    }
+}
+
+#[test]
+fn comments() {
+    for (source, expected) in [
+        ("x=1 # one\n# two\ny=2", "x = 1 # one\n# two\ny = 2\n"),
+        ("/* head */\nx = 1", "/* head */\nx = 1\n"),
+        ("x = 1 // c\n", "x = 1 // c\n"),
+        // Comments inside a statement move to its end
+        ("x = 1 + /* inline */ 2", "x = 1 + 2 /* inline */\n"),
+    ] {
+        assert_eq!(formatted(source), expected, "{source:?}");
+    }
+}
+
+#[test]
+fn idempotent() {
+    for source in [
+        "y=1+2*3",
+        "x=1 # one\n# two\ny=2",
+        "x = 1 + /* a */ 2 /* b */ # c\n\n\n/* d */ y = -(2)^2",
+        "f(n)=1 when n<2;n*f(n-1) otherwise # factorial",
+        "s=\"a{x:.3}\"+\"{{}}\" // strings",
+        "y = [1,\n  2, # two\n  3]",
+        "y = if a then b else (if c then d else e)",
+        "r = (1..=10 step 2)[0] + sum(i, 1, 3, i^2)",
+        "\n\n# only comments\n\n\n/* and more */\n",
+    ] {
+        let once = formatted(source);
+        assert_eq!(formatted(&once), once, "{source:?}");
+    }
+}
@@ -0,0 +1,89 @@
+use simple_calculator::error::CompilationErrorKind;
+use simple_calculator::tokenize::lossless::{self, TriviaContent};
+
+/// Checks that writing out the tokens of `source` gives back `source`
+fn assert_lossless(source: &str) {
+    let tokens = lossless::tokenize_lossless(source).unwrap_or_else(|e| panic!("{source:?}: {e}"));
+    assert_eq!(tokens.iter().map(ToString::to_string).collect::<String>(), source);
+}
+
+#[test]
+fn whitespace() {
+    for source in [
+        "",
+        "   ",
+        "x = 1",
+        "x = 1\n",
+        "  x=1  \n\n\ty   =   2\n",
+        "x = 1\r\ny = 2\r\n",
+        "x = (1 +\r\n  2)\r\n",
+        "\u{a0}x\u{3000}= 1\u{2028}",
+    ] {
+        assert_lossless(source);
+    }
+}
+
+#[test]
+fn unicode() {
+    for source in [
+        "π2 = π * 2",
+        "größe = 1\nÿ = größe + 1 # ñandú",
+        "y = 90° /* 角度 */ + 1",
+        r#"s = "日本語 {größe} 🎉""#,
+    ] {
+        assert_lossless(source);
+    }
+}
+
+#[test]
+fn comments() {
+    for source in [
+        "# only a comment",
+        "x = 1 # trailing\n# leading\ny = 2",
+        "x = 1 // trailing\r\n// leading\r\ny = 2",
+        "/**/x = 1",
+        "x = 1 /*/ still a comment */ + 2",
+        "x = 1 /* a *//* b */ + 2",
+        "x = 1 /*** stars ***/",
+        "x = [1, /* c */ 2 # d\n]",
+        "x = 1 #",
+    ] {
+        assert_lossless(source);
+    }
+}
+
+#[test]
+fn strings() {
+    for source in [
+        r#"s = "a\"b\\c\n\t\r\0""#,
+        r#"s = "{x:.3} and {y}""#,
+        r#"s = "{{}} \{\} {(3)}""#,
+        r#"s = "/* not a comment */ # nor this""#,
+    ] {
+        assert_lossless(source);
+    }
+}
+
+#[test]
+fn trivia_kinds() {
+    let tokens = lossless::tokenize_lossless("x = 1 /**/ /*/ */ // a\r\n").unwrap();
+    let trivia = tokens.iter().flat_map(|token| token.leading().iter().chain(token.trailing())).map(|trivia| trivia.content().clone()).collect::<Vec<_>>();
+    assert_eq!(trivia, [
+        TriviaContent::Whitespace(String::from(" ")),
+        TriviaContent::Whitespace(String::from(" ")),
+        TriviaContent::Whitespace(String::from(" ")),
+        TriviaContent::BlockComment(String::from("/**/")),
+        TriviaContent::Whitespace(String::from(" ")),
+        TriviaContent::BlockComment(String::from("/*/ */")),
+        TriviaContent::Whitespace(String::from(" ")),
+        TriviaContent::LineComment(String::from("// a\r")),
+    ]);
+}
+
+#[test]
+fn unterminated_comments() {
+    for source in ["x = 1 /* open", "x = 1 /*/", "/*"] {
+        let error = lossless::tokenize_lossless(source).expect_err(source);
+        assert!(matches!(error.kind(), CompilationErrorKind::UnterminatedComment), "{source:?}: {error}");
+    }
+}
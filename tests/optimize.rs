@@ -0,0 +1,63 @@
+use simple_calculator::ast::{self, Code};
+use simple_calculator::formatter::Printer;
+use simple_calculator::optimize::Optimizer;
+use simple_calculator::runtime::Runtime;
+use simple_calculator::tokenize;
+use simple_calculator::visit::Fold;
+
+/// Optimizes `source`, giving the optimized code and the number of errors
+/// reported
+fn optimize(source: &str) -> (String, usize) {
+    let code = tokenize::tokenize(source.chars())
+        .and_then(ast::parse)
+        .unwrap_or_else(|e| panic!("{source:?}: {e}"));
+    let runtime = Runtime::new();
+    let mut optimizer = Optimizer::new(&runtime);
+    let code: Code = optimizer.fold_code(code);
+    let printed = code.iter().map(|statement| Printer::new(None).statement(statement)).collect::<Vec<_>>().join("\n");
+    (printed, optimizer.errors().len())
+}
+
+#[test]
+fn folds_constants() {
+    for (source, expected) in [
+        ("y = 2 * 3 + x", "y = 6 + x"),
+        ("y = x + 2 * 3", "y = x + 6"),
+        ("y = [1 + 1, x]", "y = [2, x]"),
+        ("y = (1 + 2) * x", "y = 3 * x"),
+        ("y = if 1 < 2 then x else z", "y = x"),
+        // Degrees depend on the angle mode
+        ("y = 90°", "y = 90°"),
+    ] {
+        assert_eq!(optimize(source), (String::from(expected), 0), "{source:?}");
+    }
+}
+
+#[test]
+fn removes_identities() {
+    for (source, expected) in [
+        ("y = x * 1", "y = x"),
+        ("y = 1 * x", "y = x"),
+        ("y = x + 0", "y = x"),
+        ("y = 0 + f(x)", "y = f(x)"),
+        ("y = x - 0", "y = x"),
+        ("y = x / 1", "y = x"),
+        ("y = x ^ 1", "y = x"),
+        ("y = (x + 1) * (2 - 1)", "y = x + 1"),
+        // These are not numbers, so the operations change them
+        (r#"y = "a" + 0"#, r#"y = "a0""#),
+        (r#"y = "{x}" + 0"#, r#"y = "{x}" + 0"#),
+        ("y = (x == 1) * 1", "y = (x == 1) * 1"),
+        ("y = 0 - x", "y = 0 - x"),
+    ] {
+        assert_eq!(optimize(source), (String::from(expected), 0), "{source:?}");
+    }
+}
+
+#[test]
+fn leaves_errors_to_run_time() {
+    assert_eq!(optimize("y = if false then 1 / 0 else 2"), (String::from("y = 2"), 0));
+    assert_eq!(optimize("y = 1 < 2 ? 3 : 1 / 0"), (String::from("y = 3"), 0));
+    assert_eq!(optimize("y = if x then 1 / 0 else 2"), (String::from("y = if x then 1 / 0 else 2"), 1));
+    assert_eq!(optimize("y = x + 1 / 0"), (String::from("y = x + 1 / 0"), 1));
+}
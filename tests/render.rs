@@ -0,0 +1,76 @@
+use simple_calculator::ast::{self, CodeObjectContent, Expression, Value};
+use simple_calculator::render;
+use simple_calculator::runtime::Runtime;
+use simple_calculator::tokenize;
+
+fn parse(source: &str) -> Expression {
+    let code = tokenize::tokenize(format!("y = {source}").chars())
+        .and_then(ast::parse)
+        .unwrap_or_else(|e| panic!("{source:?}: {e}"));
+    match code[0].content() {
+        CodeObjectContent::Assign(_, expr) => expr.clone(),
+        statement => panic!("{source:?} was parsed as {statement:?}"),
+    }
+}
+
+fn eval(source: &str) -> Value {
+    Runtime::new().eval_expr(&parse(source)).unwrap_or_else(|e| panic!("{source:?}: {}", e.to_string()))
+}
+
+/// The MathML of an expression, without the `<math>` element around it
+fn mathml(expr: &Expression) -> String {
+    strip_math(render::mathml(expr))
+}
+
+fn strip_math(mathml: String) -> String {
+    mathml
+        .strip_prefix(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#)
+        .and_then(|mathml| mathml.strip_suffix("</math>"))
+        .unwrap_or_else(|| panic!("{mathml:?} is not a math element"))
+        .to_owned()
+}
+
+#[test]
+fn latex() {
+    for (source, expected) in [
+        ("-x^2", "- x^{2}"),
+        ("(-x)^2", r"\left(- x\right)^{2}"),
+        ("a / b * c", r"\frac{a}{b} \cdot c"),
+        ("7 div 2", r"\left\lfloor \frac{7}{2} \right\rfloor"),
+        ("sqrt(x) + 1", r"\sqrt{x} + 1"),
+        ("30°", r"30 ^{\circ}"),
+        ("[1, 2]", r"\left[1, 2\right]"),
+        (r#"x_1 + "a&b""#, r"\mathrm{x\_1} + \text{a\&b}"),
+        ("if a then 1 else 2", r"\begin{cases} 1 & \text{if } a \\ 2 & \text{otherwise} \end{cases}"),
+    ] {
+        assert_eq!(render::latex(&parse(source)), expected, "{source:?}");
+    }
+}
+
+#[test]
+fn mathml_expressions() {
+    for (source, expected) in [
+        ("-x^2", "<mrow><mo>-</mo><msup><mi>x</mi><mn>2</mn></msup></mrow>"),
+        ("a / b", "<mfrac><mi>a</mi><mi>b</mi></mfrac>"),
+        ("1 < 2", "<mrow><mn>1</mn><mo>&lt;</mo><mn>2</mn></mrow>"),
+        (r#""a&b""#, "<mtext>a&amp;b</mtext>"),
+        ("sqrt(x)", "<msqrt><mi>x</mi></msqrt>"),
+    ] {
+        assert_eq!(mathml(&parse(source)), expected, "{source:?}");
+    }
+}
+
+#[test]
+fn exact_values() {
+    for (source, latex, mathml) in [
+        ("-1 / 3", r"- \frac{1}{3}", "<mrow><mo>-</mo><mfrac><mn>1</mn><mn>3</mn></mfrac></mrow>"),
+        ("2^0.5 - 3", r"- 3 + \sqrt{2}", "<mrow><mrow><mo>-</mo><mn>3</mn></mrow><mo>+</mo><msqrt><mn>2</mn></msqrt></mrow>"),
+        ("[1, 2/3]", r"\left[1, \frac{2}{3}\right]", "<mrow><mo>[</mo><mn>1</mn><mo>,</mo><mfrac><mn>2</mn><mn>3</mn></mfrac><mo>]</mo></mrow>"),
+        ("-9223372036854775807 - 1", "- 9223372036854775808", "<mrow><mo>-</mo><mn>9223372036854775808</mn></mrow>"),
+        ("(-9223372036854775807 - 1) * sqrt(2)", r"- 9223372036854775808 \sqrt{2}", "<mrow><mo>-</mo><mrow><mn>9223372036854775808</mn><msqrt><mn>2</mn></msqrt></mrow></mrow>"),
+    ] {
+        let value = eval(source);
+        assert_eq!(render::value_to_latex(&value), latex, "{source:?}");
+        assert_eq!(strip_math(render::value_to_mathml(&value)), mathml, "{source:?}");
+    }
+}
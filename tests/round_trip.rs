@@ -0,0 +1,212 @@
+use simple_calculator::ast::{self, Code, CodeObject, Expression, ExpressionContent};
+use simple_calculator::formatter::{self, Printer};
+use simple_calculator::optimize;
+use simple_calculator::runtime::Runtime;
+use simple_calculator::tokenize;
+use simple_calculator::visit::{self, Fold};
+use simple_calculator::Location;
+
+/// Removes what printing doesn't keep, so trees can be compared: locations,
+/// and brackets, which are only printed where they are needed
+struct Normalize;
+
+impl Fold for Normalize {
+    fn fold_code_object(&mut self, statement: CodeObject) -> CodeObject {
+        let statement = visit::fold_code_object(self, statement);
+        CodeObject::new(statement.into_content(), Location::default())
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match visit::fold_expression(self, expr).into_content() {
+            ExpressionContent::Parenthases(inner) => *inner,
+            content => Expression::new(content, Location::default()),
+        }
+    }
+}
+
+fn parse(source: &str) -> Code {
+    let tokens = tokenize::tokenize(source.chars()).unwrap_or_else(|e| panic!("{source:?}: {e}"));
+    ast::parse(tokens).unwrap_or_else(|e| panic!("{source:?}: {e}"))
+}
+
+fn print(code: &Code) -> String {
+    code.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+}
+
+/// Prints code after folding its constants, when there is no source to
+/// copy literals from
+fn print_optimized(source: &str) -> String {
+    print_optimized_with(source, None)
+}
+
+fn print_optimized_with(source: &str, text: Option<&[char]>) -> String {
+    let code = optimize::optimize(parse(source), &Runtime::new());
+    code.iter().map(|statement| Printer::new(text).statement(statement)).collect::<Vec<_>>().join("\n")
+}
+
+/// The value of `y` after running `source` with `x = 3`
+fn eval(source: &str) -> String {
+    let mut runtime = Runtime::new();
+    for statement in parse(&format!("x = 3\n{source}")).iter() {
+        runtime.exec(statement).unwrap_or_else(|e| panic!("{source:?}: {}", e.to_string()));
+    }
+    runtime.resolve_variable(&String::from("y"), &Location::default()).map_or_else(|e| e.to_string(), |y| y.to_string())
+}
+
+/// Checks that printing `source` and parsing it again gives the same tree
+fn assert_round_trips(source: &str) {
+    let code = parse(source);
+    let printed = print(&code);
+    let reparsed = parse(&printed);
+    assert_eq!(
+        Normalize.fold_code(reparsed),
+        Normalize.fold_code(code),
+        "{source:?} was printed as {printed:?}",
+    );
+    assert_eq!(print(&parse(&printed)), printed, "printing {source:?} is not stable");
+}
+
+#[test]
+fn arithmetic() {
+    for source in [
+        "y = 1 + 2 * 3",
+        "y = (1 + 2) * 3",
+        "y = 1 - (2 - 3)",
+        "y = (1 - 2) - 3",
+        "y = 8 / (4 / 2)",
+        "y = 2 ^ 3 ^ 2",
+        "y = (2 ^ 3) ^ 2",
+        "y = -2 ^ 2",
+        "y = (-2) ^ 2",
+        "y = 2 ^ -1",
+        "y = --x",
+        "y = 7 mod 3 + 7 rem 3 + 7 div 3",
+        "y = 2x + 3(x + 1)",
+        "y = 1.5 + 0.25",
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn postfix() {
+    for source in [
+        "y = 3!",
+        "y = (3!)!",
+        "y = 5!!",
+        "y = (-3)!",
+        "y = -3!",
+        "y = 90°",
+        "y = xs[0][1]",
+        "y = (a + b)[0]",
+        "y = [1, 2, 3][1]",
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn logic() {
+    for source in [
+        "y = a < b and b < c or not d",
+        "y = not a == b",
+        "y = (not a) == b",
+        "y = not (a and b)",
+        "y = (a or b) and c",
+        "y = a == (b == c)",
+        "y = if a then b else c",
+        "y = (if a then b else c) + 1",
+        "y = a ? b : c ? d : e",
+        "y = (a ? b : c) ? d : e",
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn ranges() {
+    for source in [
+        "y = 1..10",
+        "y = 1..=n + 1",
+        "y = 0..1 step 0.25",
+        "y = (1..3) == xs",
+        "y = [1..3, 4]",
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn statements() {
+    for source in [
+        "f(x) = x ^ 2 + 1",
+        "f(n) = 1 when n < 2; n * f(n - 1) otherwise",
+        "print(f(2), g(1, 2))",
+        "x = [1, [2, 3], []]",
+        "x = [[1,], [2]]",
+        r#"s = "a\"b\\c\n" + "x = {x:.3} and {y}""#,
+        r#"s = format("{{}} {}", 1) + "{x} {{x}}""#,
+        r#"s = "{(3)} {x}""#,
+        "x = 1; y = 2\nz = x + y",
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn folded_literals() {
+    for (source, expected) in [
+        ("y = 1 + 2", "y = 3"),
+        ("y = 1 / 4", "y = 0.25"),
+        ("y = 1 / 3", "y = 1 / 3"),
+        ("y = -9223372036854775807 - 1", "y = -9223372036854775808"),
+        ("y = (0 - 2^0.3) ^ x", "y = (-1.2311444133449163) ^ x"),
+        ("y = (0 - 2) ^ x", "y = (-2) ^ x"),
+        ("y = x + (0 - 1) / 3", "y = x + -(1 / 3)"),
+        ("y = 2^(1/2) * x", "y = sqrt(2) * x"),
+        ("y = (1 - 2^0.5) * x", "y = (1 - sqrt(2)) * x"),
+        ("y = (1009^2 * 2)^(1/2) * x", "y = 1009 * sqrt(2) * x"),
+        ("y = (1000003^2 * 35)^(1/2) * x", "y = 1000003 * sqrt(35) * x"),
+        ("y = (1009^2 * 3 / 4)^(1/2) * x", "y = 504.5 * sqrt(3) * x"),
+        ("y = (1009 * 1013)^(1/2) * x", "y = sqrt(1022117) * x"),
+        ("y = 3 * pi / 4", "y = 0.75 * pi"),
+        ("y = x ^ (0 - e)", "y = x ^ -e"),
+        (r#"y = "{1 + 2}""#, r#"y = "3""#),
+        (r#"y = "{1 + 2}\{}" + x"#, r#"y = "3\{\}" + x"#),
+    ] {
+        assert_eq!(print_optimized(source), expected, "{source:?}");
+        // Folded literals have no source text of their own to copy
+        let chars = source.chars().collect::<Vec<_>>();
+        assert_eq!(print_optimized_with(source, Some(&chars)), expected, "{source:?}");
+    }
+
+    // `{3}` would be a placeholder for `format`
+    assert_eq!(print_optimized(r#"y = "{1 + 2} {x}""#), r#"y = "{(3)} {x}""#);
+}
+
+#[test]
+fn source_literals() {
+    for source in ["y = 0.10 * 2.50", r#"y = "a\{b" + "{x:.3} {{}}""#] {
+        let formatted = formatter::format(source).unwrap_or_else(|e| panic!("{source:?}: {e}"));
+        assert_eq!(formatted.trim_end(), source);
+    }
+}
+
+/// Folded literals, which have no source text, mean the same when printed
+#[test]
+fn folded_literals_round_trip() {
+    for source in [
+        "y = (0 - 2) ^ x",
+        "y = x - (0 - 1) / 3",
+        "y = 2^(1/2) * x",
+        "y = (1 - 2^0.5) ^ x",
+        "y = (0 - 3 * 2^0.5) ^ x",
+        "y = phi * x",
+        "y = x / (0 - pi / 2)",
+        r#"y = "{1 + 2}\{}{x}}}""#,
+    ] {
+        let printed = print_optimized(source);
+        assert_eq!(print_optimized(&printed), printed, "printing {source:?} is not stable");
+        assert_eq!(eval(&printed), eval(source), "{source:?} was printed as {printed:?}");
+    }
+}
@@ -0,0 +1,408 @@
+use simple_calculator::ast;
+use simple_calculator::number::Precision;
+use simple_calculator::runtime::{AngleMode, Runtime};
+use simple_calculator::tokenize;
+use std::time::{Duration, Instant};
+
+/// Runs `source` in `runtime`, giving the last value it prints, or the
+/// message of the first error
+fn run_in(runtime: &mut Runtime, source: &str) -> Result<String, String> {
+    let code = tokenize::tokenize(source.chars())
+        .and_then(ast::parse)
+        .map_err(|e| e.to_string())?;
+
+    let mut printed = String::new();
+    for object in code.iter() {
+        if let Some(value) = runtime.exec(object).map_err(|e| e.message())? {
+            printed = value.to_string();
+        }
+    }
+    Ok(printed)
+}
+
+fn run(source: &str) -> Result<String, String> {
+    run_in(&mut Runtime::new(), source)
+}
+
+/// The value of a single expression
+fn eval(expr: &str) -> Result<String, String> {
+    run(&format!("y = {expr}\ny"))
+}
+
+/// Checks the value of each expression, or its error
+fn assert_evals<'a>(cases: impl IntoIterator<Item = (&'a str, Result<&'a str, &'a str>)>) {
+    for (expr, expected) in cases {
+        assert_eq!(eval(expr).as_deref(), expected.map_err(String::from).as_deref(), "{expr:?}");
+    }
+}
+
+/// Runs `f` with as much stack as the main thread has, for tests that nest
+/// as deeply as the limits allow
+fn with_main_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn call_depth() {
+    let results = with_main_stack(|| {
+        let f = "f(n) = if n == 0 then 0 else 1 + f(n - 1)";
+        [run(&format!("{f}\ny = f(199)\ny")), run(&format!("{f}\ny = f(200)\ny")), run("f(n) = n * f(n)\ny = f(1)")]
+    });
+    assert_eq!(results, [
+        Ok(String::from("199")),
+        Err(String::from("Function calls nested more than 200 deep")),
+        Err(String::from("Function calls nested more than 200 deep")),
+    ]);
+
+    let mut runtime = Runtime::new();
+    runtime.set_max_call_depth(10);
+    let f = "f(n) = if n == 0 then 0 else 1 + f(n - 1)";
+    assert_eq!(run_in(&mut runtime, &format!("{f}\ny = f(9)\ny")).as_deref(), Ok("9"));
+    assert_eq!(run_in(&mut runtime, "y = f(10)\ny"), Err(String::from("Function calls nested more than 10 deep")));
+}
+
+#[test]
+fn tail_calls() {
+    for source in [
+        "f(n, a) = if n == 0 then a else f(n - 1, a + 1)\ny = f(10000, 0)\ny",
+        "f(n, a) = a when n == 0; f(n - 1, a + 1) otherwise\ny = f(10000, 0)\ny",
+        "f(n, a) = n == 0 ? a : (f(n - 1, a + 1))\ny = f(10000, 0)\ny",
+        "even(n) = if n == 0 then 10000 else odd(n - 1)\nodd(n) = if n == 0 then 0 else even(n - 1)\ny = even(10000)\ny",
+    ] {
+        assert_eq!(run(source).as_deref(), Ok("10000"), "{source:?}");
+    }
+}
+
+#[test]
+fn brackets_around_tail_calls() {
+    let result = with_main_stack(|| {
+        let body = format!("{}1 + f(n - 1){}", "(".repeat(50), ")".repeat(50));
+        run(&format!("f(n) = if n == 0 then 0 else {body}\ny = f(199)\ny"))
+    });
+    assert_eq!(result.as_deref(), Ok("199"));
+}
+
+#[test]
+fn nesting_in_function_bodies() {
+    let result = with_main_stack(|| {
+        let body = format!("0 + {}1 + f(n - 1){}", "(".repeat(200), ")".repeat(200));
+        run(&format!("f(n) = if n == 0 then 0 else {body}\ny = f(10)\ny"))
+    });
+    assert_eq!(result, Err(String::from("Expressions nested more than 800 deep")));
+}
+
+#[test]
+fn floored_division_of_large_negative_numbers() {
+    for (source, expected) in [
+        ("y = -9223372036854775807 div 3\ny", "-3074457345618258603"),
+        ("y = -9223372036854775807 % 2\ny", "1"),
+        ("y = -9223372036854775807 mod 10\ny", "3"),
+        ("y = (-9223372036854775807 - 1) div 2\ny", "-4611686018427387904"),
+        ("y = -7 div 2\ny", "-4"),
+        ("y = 7 mod -3\ny", "-2"),
+    ] {
+        assert_eq!(run(source).as_deref(), Ok(expected), "{source:?}");
+    }
+}
+
+#[test]
+fn escaped_braces() {
+    for (source, expected) in [
+        (r#"y = format("{{}}")"#, "{}"),
+        (r#"y = format("a {{b}} {}", 1)"#, "a {b} 1"),
+        (r#"y = format("\{\{}}")"#, "{}"),
+        (r#"x = 5; y = "{x} {{x}}""#, "5 {x}"),
+    ] {
+        assert_eq!(run(&format!("{source}\ny")).as_deref(), Ok(expected), "{source:?}");
+    }
+}
+
+#[test]
+fn double_slash_is_a_comment() {
+    assert_eq!(run("y = 7 div 2\ny").as_deref(), Ok("3"));
+    assert_eq!(run("y = 7 // seven\ny").as_deref(), Ok("7"));
+    assert_eq!(run("// 2 is the only even prime\ny = 2\ny").as_deref(), Ok("2"));
+    // Floor division used to be written `//`
+    for source in ["y = 7 // 2", "y = x//(2)", "y = 3! // 2"] {
+        assert!(run(source).is_err_and(|e| e.starts_with("FloorDivisionComment")), "{source:?}");
+    }
+}
+
+#[test]
+fn constants() {
+    assert_evals([
+        ("sin(pi)", Ok("0")),
+        ("cos(pi)", Ok("-1")),
+        ("sin(pi / 6)", Ok("1/2")),
+        ("cos(tau)", Ok("1")),
+        ("tan(pi / 4)", Ok("1")),
+        ("phi^2 - phi", Ok("1")),
+        ("tau == 2 * pi", Ok("true")),
+        ("π == pi and τ == tau and φ == phi", Ok("true")),
+        ("pi", Ok("3.141592653589793")),
+        ("e", Ok("2.718281828459045")),
+        ("phi", Ok("1.618033988749895")),
+        ("-inf", Ok("-inf")),
+        ("1 / inf", Ok("0")),
+    ]);
+}
+
+#[test]
+fn constants_cannot_be_assigned() {
+    for source in ["pi = 3", "e = 2", "true = false", "f(pi) = 1", "y = sum(e, 1, 2, e)"] {
+        assert!(run(source).is_err_and(|e| e.starts_with("Cannot assign to constant")), "{source:?}");
+    }
+}
+
+#[test]
+fn special_angles() {
+    assert_evals([
+        ("sin(30°)", Ok("1/2")),
+        ("cos(60°)", Ok("1/2")),
+        ("cos(90°)", Ok("0")),
+        ("tan(45°)", Ok("1")),
+        ("tan(90°)", Err("Result is undefined")),
+        ("tan(270°)", Err("Result is undefined")),
+        ("180° == pi", Ok("true")),
+        ("deg(pi)", Ok("180")),
+        ("rad(180) == pi", Ok("true")),
+    ]);
+}
+
+#[test]
+fn angle_modes() {
+    for (angle_mode, source, expected) in [
+        (AngleMode::Degrees, "sin(30)", "1/2"),
+        (AngleMode::Degrees, "asin(1/2)", "30"),
+        (AngleMode::Degrees, "atan(1)", "45"),
+        (AngleMode::Degrees, "90°", "90"),
+        (AngleMode::Gradians, "cos(100)", "0"),
+        (AngleMode::Gradians, "atan(1)", "50"),
+        (AngleMode::Gradians, "90°", "100"),
+        // Conversions don't depend on the mode
+        (AngleMode::Gradians, "deg(pi)", "180"),
+    ] {
+        let mut runtime = Runtime::new();
+        runtime.set_angle_mode(angle_mode);
+        assert_eq!(run_in(&mut runtime, &format!("y = {source}\ny")).as_deref(), Ok(expected), "{angle_mode:?} {source:?}");
+    }
+}
+
+#[test]
+fn factorials() {
+    const INVALID: &str = "Factorial is only defined for non-negative integers";
+    assert_evals([
+        ("0!", Ok("1")),
+        ("5!", Ok("120")),
+        ("20!", Ok("2432902008176640000")),
+        ("0!!", Ok("1")),
+        ("6!!", Ok("48")),
+        ("7!!", Ok("105")),
+        ("(3!)!", Ok("720")),
+        ("21!", Err("Number too large")),
+        ("34!!", Err("Number too large")),
+        ("(-1)!", Err(INVALID)),
+        ("0.5!", Err(INVALID)),
+        ("(-2)!!", Err(INVALID)),
+        ("pi!", Err(INVALID)),
+    ]);
+}
+
+#[test]
+fn big_factorials() {
+    let mut runtime = Runtime::new();
+    runtime.set_precision(Precision::Big { max_bits: 1 << 16 });
+    assert_eq!(run_in(&mut runtime, "y = 25!\ny").as_deref(), Ok("15511210043330985984000000"));
+}
+
+#[test]
+fn gamma() {
+    assert_evals([
+        ("gamma(5)", Ok("24")),
+        ("gamma(1/2)", Ok("1.7724538509055159")),
+        ("gamma(-0.5)", Ok("-3.5449077018110295")),
+        ("gamma(0)", Err("Result is undefined")),
+        ("gamma(-1)", Err("Result is undefined")),
+    ]);
+}
+
+/// Takes far longer than any test should
+const ENDLESS: &str = "y = sum(i, 1, 10^15, i)";
+
+#[test]
+fn fuel() {
+    let mut runtime = Runtime::new();
+    runtime.set_fuel(Some(1000));
+    assert_eq!(run_in(&mut runtime, "y = sum(i, 1, 10, i)\ny").as_deref(), Ok("55"));
+    assert!(runtime.fuel().is_some_and(|fuel| fuel < 1000));
+    assert_eq!(run_in(&mut runtime, ENDLESS), Err(String::from("Evaluation ran out of fuel")));
+    assert_eq!(runtime.fuel(), Some(0));
+
+    // Factorials take steps too
+    runtime.set_precision(Precision::Big { max_bits: u64::MAX });
+    runtime.set_fuel(Some(1000));
+    assert_eq!(run_in(&mut runtime, "y = 1000000!"), Err(String::from("Evaluation ran out of fuel")));
+    runtime.set_fuel(Some(1000));
+    assert_eq!(run_in(&mut runtime, "y = 1000000!!"), Err(String::from("Evaluation ran out of fuel")));
+}
+
+#[test]
+fn deadline() {
+    let mut runtime = Runtime::new();
+    runtime.set_deadline(Some(Instant::now() + Duration::from_millis(50)));
+    assert_eq!(run_in(&mut runtime, ENDLESS), Err(String::from("Evaluation took too long")));
+    assert_eq!(run_in(&mut runtime, "y = 1"), Err(String::from("Evaluation took too long")));
+
+    runtime.set_deadline(None);
+    assert_eq!(run_in(&mut runtime, "y = 1\ny").as_deref(), Ok("1"));
+}
+
+#[test]
+fn cancellation() {
+    let mut runtime = Runtime::new();
+    let handle = runtime.cancellation_handle();
+    let canceller = std::thread::spawn({
+        let handle = handle.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.cancel();
+        }
+    });
+    assert_eq!(run_in(&mut runtime, ENDLESS), Err(String::from("Evaluation was cancelled")));
+    canceller.join().unwrap();
+
+    assert!(handle.is_cancelled());
+    handle.reset();
+    assert_eq!(run_in(&mut runtime, "y = 1\ny").as_deref(), Ok("1"));
+}
+
+#[test]
+fn nesting_limits() {
+    let results = with_main_stack(|| {
+        let nested = |depth| format!("y = {}1{}\ny", "(".repeat(depth), ")".repeat(depth));
+        let chain = |len| format!("y = 0{}\ny", " + 1".repeat(len));
+        [nested(250), nested(300), chain(250), chain(300), format!("y = {}1\ny", "-".repeat(300))].map(|source| run(&source))
+    });
+    assert_eq!(results[0].as_deref(), Ok("1"));
+    assert!(results[1].as_ref().is_err_and(|e| e.starts_with("TooDeeplyNested")), "{:?}", results[1]);
+    assert_eq!(results[2].as_deref(), Ok("250"));
+    assert!(results[3].as_ref().is_err_and(|e| e.starts_with("TooDeeplyNested")), "{:?}", results[3]);
+    assert!(results[4].as_ref().is_err_and(|e| e.starts_with("TooDeeplyNested")), "{:?}", results[4]);
+
+    let mut runtime = Runtime::new();
+    runtime.set_max_depth(10);
+    assert_eq!(run_in(&mut runtime, "y = ((((((((((((1))))))))))))"), Err(String::from("Expressions nested more than 10 deep")));
+}
+
+#[test]
+fn big_precision_bit_cap() {
+    for (max_bits, source, expected) in [
+        (64, "2^62", Ok("4611686018427387904")),
+        (64, "2^64", Err("Number needs more than 64 bits")),
+        (64, "9223372036854775807 + 1", Err("Number needs more than 64 bits")),
+        (1000, "2^100", Ok("1267650600228229401496703205376")),
+        (128, "3^80 / 2^50", Err("Number needs more than 128 bits")),
+        (128, "(2^70 + 1/3) * 3", Ok("3541774862152233910273")),
+        (256, "sum(k, 1, 1000, 1/k)", Err("Number needs more than 256 bits")),
+        (1 << 16, "sum(k, 1, 30, 1/k)", Ok("9304682830147/2329089562800")),
+    ] {
+        let mut runtime = Runtime::new();
+        runtime.set_precision(Precision::Big { max_bits });
+        let result = run_in(&mut runtime, &format!("y = {source}\ny"));
+        assert_eq!(result.as_deref(), expected.map_err(String::from).as_deref(), "{max_bits} {source:?}");
+    }
+
+    assert_evals([("2^64", Err("Number too large"))]);
+}
+
+#[test]
+fn ranges() {
+    assert_evals([
+        ("1..4", Ok("[1, 2, 3]")),
+        ("1..=4", Ok("[1, 2, 3, 4]")),
+        ("1..4 step 2", Ok("[1, 3]")),
+        ("3..0 step -1", Ok("[3, 2, 1]")),
+        ("0..=1 step 0.25", Ok("[0, 1/4, 1/2, 3/4, 1]")),
+        ("0..1 step 1/3", Ok("[0, 1/3, 2/3]")),
+        ("1..1", Ok("[]")),
+        ("1..(1/2)", Ok("[]")),
+        ("(1..4)[2]", Ok("3")),
+        ("len(1..=1000)", Ok("1000")),
+    ]);
+}
+
+#[test]
+fn range_errors() {
+    assert_evals([
+        ("1..=2^20 + 2", Err("Range has more than 1048576 elements")),
+        ("1..10^18", Err("Range has more than 1048576 elements")),
+        ("0..1 step 10^-9", Err("Range has more than 1048576 elements")),
+        ("0..1 step 0", Err("The step of a range must be a non-zero number")),
+        ("0..1 step -1", Ok("[]")),
+        ("0..inf", Err("The bounds of a range must be finite")),
+        ("-inf..0", Err("The bounds of a range must be finite")),
+        (r#""a"..3"#, Err("Expected number, found string")),
+        (r#"1..4 step "a""#, Err("Expected number, found string")),
+    ]);
+}
+
+#[test]
+fn sequences() {
+    assert_eq!(run("f(i) = i^2\ny = seq(f, 4)\ny").as_deref(), Ok("[0, 1, 4, 9]"));
+    assert_eq!(run("f(i) = i^2\ny = seq(f, 0)\ny").as_deref(), Ok("[]"));
+    assert_eq!(run("f(i) = i^2\ny = seq(f, 3.5)\ny"), Err(String::from("Expected non-negative integer, found number")));
+    assert_eq!(eval("seq(sqrt, 2)").as_deref(), Ok("[0, 1]"));
+}
+
+#[test]
+fn sums_and_products() {
+    assert_evals([
+        ("sum(i, 1, 100, i^2)", Ok("338350")),
+        ("sum(i, 1, 3, 1/i)", Ok("11/6")),
+        ("sum(i, -2, 2, i)", Ok("0")),
+        ("sum(i, 1, 0, i)", Ok("0")),
+        ("sum(i, 1, 3, sum(j, 1, i, j))", Ok("10")),
+        ("prod(k, 1, 5, k)", Ok("120")),
+        ("prod(k, 1, 0, k)", Ok("1")),
+        ("prod(k, 1, 21, k)", Err("Number too large")),
+        ("sum([1, 2, 3])", Ok("6")),
+        ("prod(1..5)", Ok("24")),
+    ]);
+}
+
+#[test]
+fn one_element_lists() {
+    assert_evals([
+        ("len([5,])", Ok("1")),
+        ("[5,] * 2", Ok("[10]")),
+        ("[5,][0]", Ok("5")),
+        ("len([])", Ok("0")),
+        // Without a comma, brackets group
+        ("[5]", Ok("5")),
+        ("2 * [1 + 2]", Ok("6")),
+        ("len([5])", Err("Expected list, found number")),
+        ("len([[1, 2]])", Ok("2")),
+    ]);
+}
+
+#[test]
+fn series_bounds_must_be_integers() {
+    for bounds in ["1.5, 3", "1, 3.5", "1, pi", "1, 2^0.5", "0, inf"] {
+        assert_eq!(eval(&format!("sum(i, {bounds}, i)")), Err(String::from("Expected integer, found number")), "{bounds:?}");
+        assert_eq!(eval(&format!("prod(i, {bounds}, i)")), Err(String::from("Expected integer, found number")), "{bounds:?}");
+    }
+    assert_eq!(eval(r#"sum(i, "a", 3, i)"#), Err(String::from("Expected number, found string")));
+    assert_eq!(eval("sum(1, 1, 3, 1)"), Err(String::from("Expected index variable, found expression")));
+}
+
+#[test]
+fn series_index_is_scoped() {
+    assert_eq!(run("i = 10\ny = sum(i, 1, 3, i) + i\ny").as_deref(), Ok("16"));
+    assert_eq!(run("y = sum(i, 1, 3, i)\nz = i"), Err(String::from(r#"Could not resolve "i""#)));
+    assert_eq!(run("f(n) = sum(i, 1, n, i * n)\ny = f(3)\ny").as_deref(), Ok("18"));
+}
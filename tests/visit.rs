@@ -0,0 +1,95 @@
+use simple_calculator::ast::{self, Code, Expression, ExpressionContent, SimpleValue};
+use simple_calculator::number::Number;
+use simple_calculator::tokenize;
+use simple_calculator::visit::{self, Fold, Visitor, VisitorMut};
+
+fn parse(source: &str) -> Code {
+    tokenize::tokenize(source.chars())
+        .and_then(ast::parse)
+        .unwrap_or_else(|e| panic!("{source:?}: {e}"))
+}
+
+fn print(code: &Code) -> String {
+    code.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+}
+
+/// Records every expression visited, before or after its children
+struct Recorder {
+    post_order: bool,
+    visited: Vec<String>,
+}
+
+impl Visitor for Recorder {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if !self.post_order {
+            self.visited.push(expr.to_string());
+        }
+        visit::walk_expression(self, expr);
+        if self.post_order {
+            self.visited.push(expr.to_string());
+        }
+    }
+}
+
+fn record(source: &str, post_order: bool) -> Vec<String> {
+    let mut recorder = Recorder { post_order, visited: Vec::new() };
+    recorder.visit_code(&parse(source));
+    recorder.visited
+}
+
+#[test]
+fn visits_in_source_order() {
+    let source = "f(x) = x + 1 when x > 0; 0 otherwise\ny = [a, b[0]]\nprint(c ? \"{d}\" : 1..n step s)";
+    assert_eq!(record(source, false), [
+        "x + 1", "x", "1", "x > 0", "x", "0", "0",
+        "[a, b[0]]", "a", "b[0]", "b", "0",
+        "if c then \"{d}\" else 1..n step s", "c", "\"{d}\"", "d", "1..n step s", "1", "n", "s",
+    ].map(String::from));
+    assert_eq!(record(source, true), [
+        "x", "1", "x + 1", "x", "0", "x > 0", "0",
+        "a", "b", "0", "b[0]", "[a, b[0]]",
+        "c", "d", "\"{d}\"", "1", "n", "s", "1..n step s", "if c then \"{d}\" else 1..n step s",
+    ].map(String::from));
+}
+
+/// Renames a variable everywhere, in place
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        if let ExpressionContent::Variable(ident) = expr.content_mut() {
+            if ident == "x" {
+                *ident = String::from("z");
+            }
+        }
+        visit::walk_expression_mut(self, expr);
+    }
+}
+
+#[test]
+fn visits_mutably() {
+    let mut code = parse("f(x) = x when x > 0; -x otherwise\ny = [x, \"{x}\", (x)]");
+    Rename.visit_code_mut(&mut code);
+    // Parameters aren't expressions
+    assert_eq!(print(&code), "f(x) = z when z > 0; -z otherwise\ny = [z, \"{z}\", z]");
+}
+
+/// Replaces a variable with its value
+struct Substitute;
+
+impl Fold for Substitute {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match expr.content() {
+            ExpressionContent::Variable(ident) if ident == "x" => {
+                Expression::new(ExpressionContent::Literal(SimpleValue::Number(Number::integer(2))), *expr.location())
+            },
+            _ => visit::fold_expression(self, expr),
+        }
+    }
+}
+
+#[test]
+fn folds() {
+    let code = Substitute.fold_code(parse("y = x ^ x + f(x)[x]\nz = if x then \"{x:.2}\" else x..=10"));
+    assert_eq!(print(&code), "y = 2 ^ 2 + f(2)[2]\nz = if 2 then \"{(2):.2}\" else 2..=10");
+}